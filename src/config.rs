@@ -2,6 +2,7 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
+use std::str::FromStr;
 use toml::Value as TomlValue;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +15,8 @@ pub struct Config {
     pub loop_config: LoopConfig,
     pub archive: ArchiveConfig,
     pub lookup: LookupConfig,
+    #[serde(default)]
+    pub summary: SummaryConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +25,10 @@ pub struct ExtractionConfig {
     pub subcommand: String,
     pub args: Vec<String>,
     pub env: HashMap<String, String>,
+    /// Write the extractor's stdout to a file in `files.output_dir` and process
+    /// that file instead of searching for the newest one
+    #[serde(default)]
+    pub capture_stdout_as_input: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +37,58 @@ pub struct FilesConfig {
     pub file_glob: String,
     pub filename_timestamp_prefix: bool,
     pub stable_size_check_secs: u64,
+    /// Compare the data-row count against a `<file>.count` sidecar
+    #[serde(default)]
+    pub verify_count_sidecar: bool,
+    /// "fail" or "warn" when the sidecar count does not match
+    #[serde(default = "default_count_mismatch")]
+    pub count_mismatch: String,
+    /// Process every matching file each cycle instead of only the newest
+    #[serde(default)]
+    pub process_all: bool,
+    /// Concatenate all matching files (oldest first, repeated headers dropped) into one upload
+    #[serde(default)]
+    pub merge_matching: bool,
+    /// Refuse to upload files larger than this
+    #[serde(default)]
+    pub max_size_bytes: Option<u64>,
+    /// How far a file's mtime may lie in the future before it is flagged as clock skew
+    #[serde(default = "default_future_mtime_tolerance_secs")]
+    pub future_mtime_tolerance_secs: u64,
+    /// Skip files whose mtime is beyond the tolerance instead of only warning
+    #[serde(default)]
+    pub ignore_future_mtime: bool,
+    /// Consecutive NotFound checks before a file is treated as gone
+    #[serde(default = "default_stable_missing_retries")]
+    pub stable_missing_retries: u32,
+    /// Also search subdirectories of output_dir (e.g. one folder per plant)
+    #[serde(default)]
+    pub recursive: bool,
+    /// Rename a selected file to `<name><suffix>` while it is processed, so an
+    /// overlapping cycle skips it
+    #[serde(default)]
+    pub in_progress_suffix: Option<String>,
+    /// Skip a file whose content matches the last successfully processed one
+    #[serde(default)]
+    pub skip_if_unchanged: bool,
+    /// Where the runner keeps state between runs
+    #[serde(default = "default_state_path")]
+    pub state_path: String,
+    /// Keep only the newest N processed files in the state file; 0 keeps all
+    #[serde(default)]
+    pub state_max_entries: usize,
+    /// Drop processed files recorded more than N days ago; 0 keeps all
+    #[serde(default)]
+    pub state_max_age_days: u64,
+    /// Treat `file_glob` as a regex matched against the whole file name
+    #[serde(default)]
+    pub glob_is_regex: bool,
+    /// File names matching any of these are never selected (regexes with `glob_is_regex`)
+    #[serde(default)]
+    pub exclude_glob: Vec<String>,
+    /// Order of `find_all_files`: "newest_first", "oldest_first", or "name_asc"
+    #[serde(default = "default_process_order")]
+    pub process_order: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,6 +100,69 @@ pub struct TransformConfig {
     pub dedupe_rows: bool,
     pub trim_whitespace: bool,
     pub output_line_ending: String,
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    /// Also upload the untransformed original to `original_endpoint`
+    #[serde(default)]
+    pub upload_original_too: bool,
+    #[serde(default)]
+    pub original_endpoint: String,
+    /// Rows with fewer tab-separated columns go to a reject file instead of the output
+    #[serde(default)]
+    pub min_columns: Option<usize>,
+    /// Drop a final data line without a terminating newline; it may still be being written
+    #[serde(default)]
+    pub drop_incomplete_last_line: bool,
+    /// Write `<file>.report.json` describing each transform to files.output_dir
+    #[serde(default)]
+    pub write_report: bool,
+    /// Send the report as the `transform_report` multipart field with the upload
+    #[serde(default)]
+    pub upload_report: bool,
+    /// The transformed output's header line must equal this, or the upload is aborted
+    #[serde(default)]
+    pub assert_header: Option<String>,
+    /// The first transformed data row must have exactly this many columns
+    #[serde(default)]
+    pub assert_columns: Option<usize>,
+    /// Per-cell edits as "op:column[:arg]": `uppercase:0`, `trim:1`, `zeropad:2:10`
+    #[serde(default)]
+    pub row_ops: Vec<String>,
+    /// When no data rows remain after the transform, skip the upload and leave the file
+    #[serde(default)]
+    pub skip_upload_if_empty: bool,
+    /// Fail on input that is not valid UTF-8 instead of decoding it as Windows-1252
+    #[serde(default)]
+    pub encoding_strict: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationConfig {
+    #[serde(default)]
+    pub rules: Vec<ColumnRule>,
+    #[serde(default = "default_on_error")]
+    pub on_error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnRule {
+    /// Zero-based column index in the output row
+    pub column: usize,
+    #[serde(default)]
+    pub regex: Option<String>,
+    #[serde(default)]
+    pub min_length: Option<usize>,
+    #[serde(default)]
+    pub max_length: Option<usize>,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            on_error: default_on_error(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,12 +177,111 @@ pub struct ApiConfig {
     pub bearer_token: String,
     pub basic_username: String,
     pub basic_password: String,
+    #[serde(default = "default_chunk_size_bytes")]
+    pub chunk_size_bytes: u64,
+    /// Add file size and modification time to json_base64 payloads
+    #[serde(default)]
+    pub include_file_metadata: bool,
+    #[serde(default = "default_json_size_key")]
+    pub json_size_key: String,
+    #[serde(default = "default_json_modified_key")]
+    pub json_modified_key: String,
+    /// Tried with its own retry budget once the primary endpoint gives up
+    #[serde(default)]
+    pub fallback_endpoint: Option<String>,
+    /// JSON payload shape for json_base64 with `{{filename}}`, `{{data}}`, and
+    /// `{{field:name}}` placeholders; replaces the flat key layout when set
+    #[serde(default)]
+    pub json_template: Option<String>,
+    /// JSON Schema file a successful response body must match, or the upload fails
+    #[serde(default)]
+    pub response_schema: Option<String>,
+    /// Fixed upload filename, replacing the local name; `{date}` is today's date
+    /// and `{name}` a named group of the regex file_glob
+    #[serde(default)]
+    pub override_filename: Option<String>,
+    /// Uploads in flight at once when `files.process_all` is set
+    #[serde(default = "default_upload_concurrency")]
+    pub upload_concurrency: usize,
+    /// Replace characters outside `filename_allowed_chars` with underscores
+    #[serde(default)]
+    pub sanitize_filename: bool,
+    /// Characters allowed in addition to ASCII letters and digits
+    #[serde(default = "default_filename_allowed_chars")]
+    pub filename_allowed_chars: String,
+    /// Follow 3xx responses from the upload endpoint, resending the body
+    #[serde(default)]
+    pub follow_redirects: bool,
+    /// Timeout applied to each individual HTTP request
+    #[serde(default = "default_attempt_timeout_secs")]
+    pub attempt_timeout_secs: u64,
+    /// Overall budget across all attempts and backoffs for one file
+    #[serde(default)]
+    pub total_timeout_secs: Option<u64>,
+    /// How json_base64 mode encodes the file content: "base64" or "utf8" (raw text)
+    #[serde(default = "default_json_encoding")]
+    pub json_encoding: String,
+    /// Keep idle connections this long so loop iterations can reuse them
+    #[serde(default = "default_pool_idle_timeout_secs")]
+    pub pool_idle_timeout_secs: u64,
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+    /// Shell command whose stdout is the bearer token; overrides `bearer_token`
+    #[serde(default)]
+    pub bearer_token_command: Option<String>,
+    /// Re-run `bearer_token_command` before each run for short-lived tokens
+    #[serde(default)]
+    pub bearer_token_refresh: bool,
+    /// Largest json_base64 request body the gateway accepts
+    #[serde(default)]
+    pub max_body_bytes: Option<u64>,
+    /// "fail" or "warn" when the encoded body exceeds max_body_bytes
+    #[serde(default = "default_body_limit_action")]
+    pub body_limit_action: String,
+    /// Sent as If-Match on resumable PUTs so a newer version is not overwritten
+    #[serde(default)]
+    pub if_match: Option<String>,
+    /// Sent as If-None-Match on resumable PUTs (e.g. "*" to only create)
+    #[serde(default)]
+    pub if_none_match: Option<String>,
+    /// Content-Type of the multipart file part; inferred from the extension when unset
+    #[serde(default)]
+    pub file_mime: Option<String>,
+    /// Header carrying a fresh UUID per upload attempt; empty disables it
+    #[serde(default = "default_correlation_header")]
+    pub correlation_header: String,
+    /// Where `mode = "file"` writes each upload instead of sending it
+    #[serde(default)]
+    pub sink_dir: String,
+    /// On a 409, DELETE the partial upload at `cleanup_endpoint` and retry
+    #[serde(default)]
+    pub cleanup_on_conflict: bool,
+    /// Receives the DELETE with the upload name as the `filename` query parameter
+    #[serde(default)]
+    pub cleanup_endpoint: String,
+    /// "auto" (ALPN), "http1", or "http2"
+    #[serde(default = "default_http_version")]
+    pub http_version: String,
+    /// Lowest TLS version to negotiate: "1.2" or "1.3"
+    #[serde(default)]
+    pub min_tls_version: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RetryConfig {
     pub max_attempts: u32,
     pub initial_backoff_secs: u64,
+    /// When false, only uploads that are safe to repeat (resumable chunk PUTs)
+    /// are retried, and multipart and json_base64 POSTs fail on the first error.
+    /// The default true retries every mode.
+    #[serde(default = "default_true")]
+    pub idempotent_only: bool,
+    /// Skip the remaining uploads of a cycle after this many failed uploads in a row
+    #[serde(default)]
+    pub circuit_break_after: Option<u32>,
+    /// Longest wait honored from a server's `Retry-After` before a retry
+    #[serde(default = "default_max_retry_after_secs")]
+    pub max_retry_after_secs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +289,58 @@ pub struct LoopConfig {
     #[serde(rename = "interval_seconds")]
     pub interval_seconds: u64,
     pub allow_nested: bool,
+    /// Cron expression (sec min hour day-of-month month day-of-week [year]);
+    /// takes precedence over `interval_seconds` when set
+    #[serde(default)]
+    pub cron: Option<String>,
+    /// Start runs on a fixed `interval_seconds` cadence instead of sleeping after each run
+    #[serde(default)]
+    pub fixed_rate: bool,
+    /// Wait a random 0..=splay seconds before the first run, to spread a fleet's load
+    #[serde(default)]
+    pub startup_splay_secs: Option<u64>,
+    /// After a restart, wait out the rest of `interval_seconds` since the last
+    /// successful run (kept in `files.state_path`) instead of running at once
+    #[serde(default)]
+    pub resume_interval: bool,
+    /// Log "idle, next run in Xs" at this cadence while waiting between runs
+    #[serde(default)]
+    pub heartbeat_secs: Option<u64>,
+    /// While this file exists, loop iterations are skipped until it is removed
+    #[serde(default)]
+    pub pause_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SummaryConfig {
+    pub enabled: bool,
+    /// JSON Lines file; one summary is appended per run
+    pub path: String,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "run_summary.jsonl".to_string(),
+        }
+    }
+}
+
+impl LoopConfig {
+    pub fn schedule(&self) -> Result<Option<cron::Schedule>> {
+        self.cron
+            .as_deref()
+            .map(|expr| {
+                cron::Schedule::from_str(expr)
+                    .with_context(|| format!("Invalid loop_config.cron expression: {}", expr))
+            })
+            .transpose()
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.interval_seconds > 0 || self.cron.is_some()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,6 +348,18 @@ pub struct ArchiveConfig {
     pub enabled: bool,
     pub path: String,
     pub append_timestamp: bool,
+    /// What to do with a file whose upload failed: "leave", "quarantine", or "archive"
+    #[serde(default = "default_on_failure")]
+    pub on_failure: String,
+    #[serde(default)]
+    pub quarantine_path: String,
+    /// After a move, wait up to this long for the source to disappear from
+    /// the output folder (network shares can list it briefly afterwards)
+    #[serde(default)]
+    pub settle_ms: u64,
+    /// Log where files would be archived or quarantined without moving them
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -85,12 +370,286 @@ pub struct LookupConfig {
     pub cookie: String,
     pub timeout_secs: u64,
     pub post_url: String,
+    /// Issue a tiny lookup at startup to verify the endpoint is reachable
+    #[serde(default)]
+    pub preflight: bool,
+    /// Fill duns/cof/country with `unmatched_sentinel` when a part had no lookup hit
+    #[serde(default)]
+    pub mark_unmatched: bool,
+    #[serde(default = "default_unmatched_sentinel")]
+    pub unmatched_sentinel: String,
+    /// Quote character for TSV fields; empty disables quote handling
+    #[serde(default = "default_tsv_quote")]
+    pub tsv_quote: String,
+    /// Write a timestamped copy of the enriched rows here before posting
+    #[serde(default)]
+    pub output_file: Option<String>,
+    /// Post enriched rows to `post_url`; disable for dump-only validation runs
+    #[serde(default = "default_true")]
+    pub post: bool,
+    /// Field names tried, in order, as the part number key in array responses
+    #[serde(default = "default_array_key_fields")]
+    pub array_key_fields: Vec<String>,
+    /// Normalizations applied in order to query and response part numbers before matching
+    #[serde(default)]
+    pub key_normalize: Vec<String>,
+    /// Auth for lookup GETs; when unset, `cookie` is sent if non-empty
+    #[serde(default)]
+    pub read_auth: Option<LookupAuthConfig>,
+    /// Auth for the enriched-data POST; when unset, `cookie` is sent if non-empty
+    #[serde(default)]
+    pub post_auth: Option<LookupAuthConfig>,
+    /// Fail when parts were queried but none matched (data not ready yet)
+    #[serde(default)]
+    pub fail_on_empty: bool,
+    /// Source column index for each row field; empty keeps the positional parsing
+    #[serde(default)]
+    pub field_columns: HashMap<String, usize>,
+    /// Named cookies seeded into the lookup client's cookie store for the lookup URL
+    #[serde(default)]
+    pub cookies: HashMap<String, String>,
+    /// Post at most this many rows per request; 0 posts everything at once
+    #[serde(default)]
+    pub post_chunk_size: usize,
+    /// Pause between lookup chunk requests, to pace a rate-limited lookup service
+    #[serde(default)]
+    pub chunk_delay_ms: u64,
+    /// Random extra 0..=chunk_jitter_ms added to each pause
+    #[serde(default)]
+    pub chunk_jitter_ms: u64,
+    /// Lookup results saved here after every chunk, so an interrupted run can skip resolved parts
+    #[serde(default)]
+    pub progress_path: Option<String>,
+    /// Splits a material cell listing several parts into one row per part
+    #[serde(default)]
+    pub split_parts_delimiter: Option<String>,
+    /// Treat an HTML response (an expired session's login page) as an auth failure
+    #[serde(default)]
+    pub detect_login_html: bool,
+    /// Gzip each post body and send `Content-Encoding: gzip`
+    #[serde(default)]
+    pub post_compress: bool,
+    /// Attempts per post request, retried on connection errors, 429, and 5xx
+    #[serde(default = "default_post_max_attempts")]
+    pub post_max_attempts: u32,
+    /// "form" posts a `tableData` JSON array; "jsonl" posts one row per line as `application/x-ndjson`
+    #[serde(default = "default_post_format")]
+    pub post_format: String,
+    /// Row fields masked to their last 4 characters in log output; posted data is unchanged
+    #[serde(default)]
+    pub log_redact_fields: Vec<String>,
+    /// Same as `api.http_version`, for the lookup client
+    #[serde(default = "default_http_version")]
+    pub http_version: String,
+    /// Same as `api.min_tls_version`, for the lookup client
+    #[serde(default)]
+    pub min_tls_version: Option<String>,
+}
+
+/// Row fields that `lookup.field_columns` can fill from the source file
+pub const LOOKUP_ROW_FIELDS: &[&str] = &[
+    "plant", "delivery", "part_no", "duns", "cof", "country", "shipment",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LookupAuthConfig {
+    /// "none", "cookie", "bearer", or "basic"
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub cookie: String,
+    #[serde(default)]
+    pub bearer_token: String,
+    #[serde(default)]
+    pub basic_username: String,
+    #[serde(default)]
+    pub basic_password: String,
+}
+
+impl LookupAuthConfig {
+    fn validate(&self, name: &str) -> Result<()> {
+        match self.kind.as_str() {
+            "none" => {}
+            "cookie" if self.cookie.is_empty() => {
+                anyhow::bail!(
+                    "lookup.{}.cookie cannot be empty when type is 'cookie'",
+                    name
+                )
+            }
+            "bearer" if self.bearer_token.is_empty() => {
+                anyhow::bail!(
+                    "lookup.{}.bearer_token cannot be empty when type is 'bearer'",
+                    name
+                )
+            }
+            "basic" if self.basic_username.is_empty() => {
+                anyhow::bail!(
+                    "lookup.{}.basic_username cannot be empty when type is 'basic'",
+                    name
+                )
+            }
+            "cookie" | "bearer" | "basic" => {}
+            _ => anyhow::bail!(
+                "lookup.{}.type must be 'none', 'cookie', 'bearer', or 'basic'",
+                name
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Checks the `http_version` and `min_tls_version` pair of a config section.
+fn validate_protocol(
+    section: &str,
+    http_version: &str,
+    min_tls_version: Option<&str>,
+) -> Result<()> {
+    if !["auto", "http1", "http2"].contains(&http_version) {
+        anyhow::bail!(
+            "{}.http_version must be 'auto', 'http1', or 'http2'",
+            section
+        );
+    }
+    if min_tls_version.is_some_and(|version| !["1.2", "1.3"].contains(&version)) {
+        anyhow::bail!("{}.min_tls_version must be '1.2' or '1.3'", section);
+    }
+    Ok(())
+}
+
+fn default_http_version() -> String {
+    "auto".to_string()
+}
+
+fn default_on_failure() -> String {
+    "leave".to_string()
+}
+
+fn default_array_key_fields() -> Vec<String> {
+    vec![
+        "part".to_string(),
+        "part_no".to_string(),
+        "material".to_string(),
+    ]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_post_max_attempts() -> u32 {
+    1
+}
+
+fn default_post_format() -> String {
+    "form".to_string()
+}
+
+fn default_tsv_quote() -> String {
+    "\"".to_string()
+}
+
+fn default_unmatched_sentinel() -> String {
+    "NOT_FOUND".to_string()
+}
+
+fn default_state_path() -> String {
+    "runner_state.json".to_string()
+}
+
+fn default_stable_missing_retries() -> u32 {
+    3
+}
+
+fn default_future_mtime_tolerance_secs() -> u64 {
+    300
+}
+
+fn default_count_mismatch() -> String {
+    "fail".to_string()
+}
+
+fn default_process_order() -> String {
+    "newest_first".to_string()
+}
+
+fn default_on_error() -> String {
+    "warn".to_string()
+}
+
+fn default_chunk_size_bytes() -> u64 {
+    5 * 1024 * 1024
+}
+
+fn default_json_size_key() -> String {
+    "size".to_string()
+}
+
+fn default_json_modified_key() -> String {
+    "modified".to_string()
+}
+
+fn default_pool_idle_timeout_secs() -> u64 {
+    600
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    4
+}
+
+fn default_correlation_header() -> String {
+    "X-Correlation-Id".to_string()
+}
+
+fn default_body_limit_action() -> String {
+    "fail".to_string()
+}
+
+fn default_json_encoding() -> String {
+    "base64".to_string()
+}
+
+fn default_attempt_timeout_secs() -> u64 {
+    30
+}
+
+fn default_max_retry_after_secs() -> u64 {
+    60
+}
+
+fn default_upload_concurrency() -> usize {
+    1
+}
+
+fn default_filename_allowed_chars() -> String {
+    "._-".to_string()
 }
 
 impl Config {
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+    /// Loads the base config and, when `env` is set, deep-merges `<stem>.<env>.toml`
+    /// from the same directory over it.
+    pub fn load<P: AsRef<Path>>(path: P, env: Option<&str>) -> Result<Self> {
         let path_ref = path.as_ref();
-        let content = std::fs::read_to_string(&path)
+        let mut root = Self::read_normalized(path_ref)?;
+
+        if let Some(env) = env {
+            let stem = path_ref
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("config");
+            let overlay_path = path_ref.with_file_name(format!("{}.{}.toml", stem, env));
+            let overlay = Self::read_normalized(&overlay_path)?;
+            merge_toml(&mut root, overlay);
+        }
+
+        let config: Config = root
+            .try_into()
+            .with_context(|| "Failed to map configuration to structs")?;
+
+        Ok(config)
+    }
+
+    fn read_normalized(path_ref: &Path) -> Result<TomlValue> {
+        let content = std::fs::read_to_string(path_ref)
             .with_context(|| format!("Failed to read config file: {}", path_ref.display()))?;
         // Parse to TOML value to normalize legacy/misplaced fields before strict deserialization
         let mut root: TomlValue =
@@ -115,11 +674,7 @@ impl Config {
             }
         }
 
-        let config: Config = root
-            .try_into()
-            .with_context(|| "Failed to map configuration to structs")?;
-
-        Ok(config)
+        Ok(root)
     }
 
     pub fn validate(&self) -> Result<()> {
@@ -138,6 +693,42 @@ impl Config {
         if self.files.file_glob.is_empty() {
             anyhow::bail!("files.file_glob cannot be empty");
         }
+        if !["fail", "warn"].contains(&self.files.count_mismatch.as_str()) {
+            anyhow::bail!("files.count_mismatch must be 'fail' or 'warn'");
+        }
+        if !["newest_first", "oldest_first", "name_asc"]
+            .contains(&self.files.process_order.as_str())
+        {
+            anyhow::bail!(
+                "files.process_order must be 'newest_first', 'oldest_first', or 'name_asc'"
+            );
+        }
+
+        if let Some(suffix) = &self.files.in_progress_suffix {
+            if suffix.is_empty() || suffix.contains(['/', '\\']) {
+                anyhow::bail!(
+                    "files.in_progress_suffix must be non-empty and cannot contain path separators"
+                );
+            }
+        }
+
+        if self.files.merge_matching && self.files.skip_if_unchanged {
+            anyhow::bail!("files.merge_matching cannot be combined with files.skip_if_unchanged");
+        }
+
+        if self.files.merge_matching && self.files.process_all {
+            anyhow::bail!("files.merge_matching cannot be combined with files.process_all");
+        }
+
+        if self.files.merge_matching && self.files.verify_count_sidecar {
+            anyhow::bail!(
+                "files.merge_matching cannot be combined with files.verify_count_sidecar"
+            );
+        }
+
+        if self.files.stable_missing_retries == 0 {
+            anyhow::bail!("files.stable_missing_retries must be greater than 0");
+        }
 
         // Validate transform config
         if !["tsv", "csv"].contains(&self.transform.format.as_str()) {
@@ -146,35 +737,271 @@ impl Config {
         if !["crlf", "lf"].contains(&self.transform.output_line_ending.as_str()) {
             anyhow::bail!("transform.output_line_ending must be 'crlf' or 'lf'");
         }
+        if self.api.include_file_metadata && self.api.json_template.is_some() {
+            anyhow::bail!(
+                "api.include_file_metadata has no effect with api.json_template; use {{{{size}}}} and {{{{modified}}}} in the template"
+            );
+        }
+        if self.api.response_schema.is_some()
+            && !["multipart", "json_base64"].contains(&self.api.mode.as_str())
+        {
+            anyhow::bail!("api.response_schema requires api.mode = 'multipart' or 'json_base64'");
+        }
+        if self.transform.upload_report && self.api.mode != "multipart" {
+            anyhow::bail!("transform.upload_report requires api.mode = 'multipart'");
+        }
+        if (self.transform.assert_header.is_some() || self.transform.assert_columns.is_some())
+            && !self.transform.enabled
+        {
+            anyhow::bail!("transform.assert_header and assert_columns require transform.enabled");
+        }
+        if self.transform.skip_upload_if_empty && !self.transform.enabled {
+            anyhow::bail!("transform.skip_upload_if_empty requires transform.enabled");
+        }
+        if self.transform.encoding_strict && !self.transform.enabled {
+            anyhow::bail!("transform.encoding_strict requires transform.enabled");
+        }
+        if self.transform.assert_columns == Some(0) {
+            anyhow::bail!("transform.assert_columns must be greater than 0");
+        }
+        if !["drop", "fail", "warn"].contains(&self.transform.validation.on_error.as_str()) {
+            anyhow::bail!("transform.validation.on_error must be 'drop', 'fail', or 'warn'");
+        }
+        if self.transform.upload_original_too && self.transform.original_endpoint.is_empty() {
+            anyhow::bail!(
+                "transform.original_endpoint cannot be empty when upload_original_too is enabled"
+            );
+        }
 
         // Validate API config
-        if self.api.endpoint.is_empty() {
+        if self.api.endpoint.is_empty() && self.api.mode != "file" {
             anyhow::bail!("api.endpoint cannot be empty");
         }
-        if !["multipart", "json_base64", "lookup_enrich"].contains(&self.api.mode.as_str()) {
-            anyhow::bail!("api.mode must be 'multipart', 'json_base64', or 'lookup_enrich'");
+        if self.api.upload_concurrency == 0 {
+            anyhow::bail!("api.upload_concurrency must be greater than 0");
+        }
+
+        if !["fail", "warn"].contains(&self.api.body_limit_action.as_str()) {
+            anyhow::bail!("api.body_limit_action must be 'fail' or 'warn'");
+        }
+
+        if !["base64", "utf8"].contains(&self.api.json_encoding.as_str()) {
+            anyhow::bail!("api.json_encoding must be 'base64' or 'utf8'");
+        }
+
+        if self.api.attempt_timeout_secs == 0 {
+            anyhow::bail!("api.attempt_timeout_secs must be greater than 0");
+        }
+
+        if self.api.total_timeout_secs == Some(0) {
+            anyhow::bail!("api.total_timeout_secs must be greater than 0");
+        }
+        if matches!(&self.api.fallback_endpoint, Some(fallback) if fallback.is_empty()) {
+            anyhow::bail!("api.fallback_endpoint cannot be empty when set");
         }
+        if ![
+            "multipart",
+            "json_base64",
+            "lookup_enrich",
+            "resumable",
+            "file",
+        ]
+        .contains(&self.api.mode.as_str())
+        {
+            anyhow::bail!(
+                "api.mode must be 'multipart', 'json_base64', 'lookup_enrich', 'resumable', or 'file'"
+            );
+        }
+        if self.api.mode == "file" && self.api.sink_dir.is_empty() {
+            anyhow::bail!("api.sink_dir cannot be empty in file mode");
+        }
+        if self.api.mode == "file" && self.transform.upload_original_too {
+            anyhow::bail!("transform.upload_original_too is not supported in file mode");
+        }
+        if self.api.cleanup_on_conflict && self.api.cleanup_endpoint.is_empty() {
+            anyhow::bail!(
+                "api.cleanup_endpoint cannot be empty when cleanup_on_conflict is enabled"
+            );
+        }
+        if self.api.mode == "resumable" && self.api.chunk_size_bytes == 0 {
+            anyhow::bail!("api.chunk_size_bytes must be greater than 0 in resumable mode");
+        }
+        validate_protocol(
+            "api",
+            &self.api.http_version,
+            self.api.min_tls_version.as_deref(),
+        )?;
         if !["none", "bearer", "basic"].contains(&self.api.auth.as_str()) {
             anyhow::bail!("api.auth must be 'none', 'bearer', or 'basic'");
         }
 
+        // Validate archive config
+        let untokenized = ["{year}", "{month}", "{day}"]
+            .iter()
+            .fold(self.archive.path.clone(), |path, token| {
+                path.replace(token, "")
+            });
+        if untokenized.contains(['{', '}']) {
+            anyhow::bail!("archive.path only supports the {{year}}, {{month}}, and {{day}} tokens");
+        }
+
+        if !["leave", "quarantine", "archive"].contains(&self.archive.on_failure.as_str()) {
+            anyhow::bail!("archive.on_failure must be 'leave', 'quarantine', or 'archive'");
+        }
+        if self.archive.on_failure == "quarantine" && self.archive.quarantine_path.is_empty() {
+            anyhow::bail!(
+                "archive.quarantine_path cannot be empty when on_failure is 'quarantine'"
+            );
+        }
+
         // Validate lookup config
         if self.lookup.enabled {
             if self.lookup.url.is_empty() {
                 anyhow::bail!("lookup.url cannot be empty when lookup is enabled");
             }
-            if self.lookup.post_url.is_empty() {
+            if self.lookup.post && self.lookup.post_url.is_empty() {
                 anyhow::bail!("lookup.post_url cannot be empty when lookup is enabled");
             }
             if self.lookup.chunk_size == 0 {
                 anyhow::bail!("lookup.chunk_size must be greater than 0");
             }
+            for step in &self.lookup.key_normalize {
+                if !["strip_leading_zeros", "uppercase", "trim"].contains(&step.as_str()) {
+                    anyhow::bail!(
+                        "lookup.key_normalize entries must be 'strip_leading_zeros', 'uppercase', or 'trim'"
+                    );
+                }
+            }
+            if let Some(auth) = &self.lookup.read_auth {
+                auth.validate("read_auth")?;
+            }
+            if let Some(auth) = &self.lookup.post_auth {
+                auth.validate("post_auth")?;
+            }
+            if self.lookup.array_key_fields.is_empty() {
+                anyhow::bail!("lookup.array_key_fields cannot be empty");
+            }
+            if !self.lookup.post && self.lookup.output_file.is_none() {
+                anyhow::bail!("lookup.output_file must be set when lookup.post is false");
+            }
+            for field in self.lookup.field_columns.keys() {
+                if !LOOKUP_ROW_FIELDS.contains(&field.as_str()) {
+                    anyhow::bail!(
+                        "lookup.field_columns keys must be one of: {}",
+                        LOOKUP_ROW_FIELDS.join(", ")
+                    );
+                }
+            }
+            for field in &self.lookup.log_redact_fields {
+                if !LOOKUP_ROW_FIELDS.contains(&field.as_str()) {
+                    anyhow::bail!(
+                        "lookup.log_redact_fields entries must be one of: {}",
+                        LOOKUP_ROW_FIELDS.join(", ")
+                    );
+                }
+            }
+            if !self.lookup.field_columns.is_empty()
+                && !self.lookup.field_columns.contains_key("part_no")
+            {
+                anyhow::bail!("lookup.field_columns must map part_no when set");
+            }
+            if self.lookup.post_max_attempts == 0 {
+                anyhow::bail!("lookup.post_max_attempts must be greater than 0");
+            }
+            if self.lookup.split_parts_delimiter.as_deref() == Some("") {
+                anyhow::bail!("lookup.split_parts_delimiter must not be empty");
+            }
+            if !matches!(self.lookup.post_format.as_str(), "form" | "jsonl") {
+                anyhow::bail!("lookup.post_format must be 'form' or 'jsonl'");
+            }
+            if !self.lookup.cookies.is_empty() && !self.lookup.cookie.is_empty() {
+                anyhow::bail!("lookup.cookie and lookup.cookies cannot both be set");
+            }
+            for (name, value) in &self.lookup.cookies {
+                if name.is_empty() || name.contains(['=', ';']) || value.contains(';') {
+                    anyhow::bail!(
+                        "lookup.cookies names must be non-empty without '=' or ';', and values cannot contain ';'"
+                    );
+                }
+            }
+            if self.lookup.tsv_quote.len() > 1 || !self.lookup.tsv_quote.is_ascii() {
+                anyhow::bail!("lookup.tsv_quote must be a single ASCII character or empty");
+            }
+            validate_protocol(
+                "lookup",
+                &self.lookup.http_version,
+                self.lookup.min_tls_version.as_deref(),
+            )?;
+        }
+
+        // With a regex file_glob, `{name}` in an extra field must name one of its
+        // groups; plain globs have no groups, so braces there are sent as written
+        let capture_names: Vec<String> = if self.files.glob_is_regex {
+            regex::Regex::new(&self.files.file_glob)
+                .map(|regex| {
+                    regex
+                        .capture_names()
+                        .flatten()
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        let placeholder = regex::Regex::new(r"\{(\w+)\}").unwrap();
+        if self.files.glob_is_regex {
+            for (key, value) in &self.api.extra_fields {
+                for found in placeholder.captures_iter(value) {
+                    if !capture_names.iter().any(|name| name == &found[1]) {
+                        anyhow::bail!(
+                            "api.extra_fields.{} uses {{{}}}, but files.file_glob has no (?P<{}>...) group",
+                            key,
+                            &found[1],
+                            &found[1]
+                        );
+                    }
+                }
+            }
+        }
+        if let Some(name) = &self.api.override_filename {
+            if name.is_empty() || name.contains(['/', '\\']) {
+                anyhow::bail!(
+                    "api.override_filename must be non-empty and cannot contain path separators"
+                );
+            }
+            for found in placeholder.captures_iter(name) {
+                if &found[1] != "date" && !capture_names.iter().any(|group| group == &found[1]) {
+                    anyhow::bail!(
+                        "api.override_filename uses {{{}}}, which is neither {{date}} nor a (?P<{}>...) group of files.file_glob",
+                        &found[1],
+                        &found[1]
+                    );
+                }
+            }
+        }
+
+        // Validate loop config
+        self.loop_config.schedule()?;
+        if self.loop_config.pause_file.as_deref() == Some("") {
+            anyhow::bail!("loop_config.pause_file must not be empty");
+        }
+        if self.loop_config.heartbeat_secs == Some(0) {
+            anyhow::bail!("loop_config.heartbeat_secs must be greater than 0");
+        }
+
+        // Validate summary config
+        if self.summary.enabled && self.summary.path.is_empty() {
+            anyhow::bail!("summary.path cannot be empty when summary is enabled");
         }
 
         // Validate retry config
         if self.retry.max_attempts == 0 {
             anyhow::bail!("retry.max_attempts must be greater than 0");
         }
+        if self.retry.circuit_break_after == Some(0) {
+            anyhow::bail!("retry.circuit_break_after must be greater than 0");
+        }
 
         Ok(())
     }
@@ -193,12 +1020,30 @@ impl Default for Config {
                     "plant,material,delivery".to_string(),
                 ],
                 env: HashMap::new(),
+                capture_stdout_as_input: false,
             },
             files: FilesConfig {
                 output_dir: "C:\\sap\\outputs".to_string(),
                 file_glob: "*_y_149-ALL.txt".to_string(),
                 filename_timestamp_prefix: true,
                 stable_size_check_secs: 2,
+                verify_count_sidecar: false,
+                count_mismatch: default_count_mismatch(),
+                process_all: false,
+                merge_matching: false,
+                max_size_bytes: None,
+                future_mtime_tolerance_secs: default_future_mtime_tolerance_secs(),
+                ignore_future_mtime: false,
+                stable_missing_retries: default_stable_missing_retries(),
+                recursive: false,
+                in_progress_suffix: None,
+                skip_if_unchanged: false,
+                state_path: default_state_path(),
+                state_max_entries: 0,
+                state_max_age_days: 0,
+                glob_is_regex: false,
+                exclude_glob: Vec::new(),
+                process_order: default_process_order(),
             },
             transform: TransformConfig {
                 enabled: false,
@@ -208,6 +1053,18 @@ impl Default for Config {
                 dedupe_rows: false,
                 trim_whitespace: true,
                 output_line_ending: "crlf".to_string(),
+                validation: ValidationConfig::default(),
+                upload_original_too: false,
+                original_endpoint: String::new(),
+                min_columns: None,
+                drop_incomplete_last_line: false,
+                write_report: false,
+                upload_report: false,
+                assert_header: None,
+                assert_columns: None,
+                row_ops: Vec::new(),
+                skip_upload_if_empty: false,
+                encoding_strict: false,
             },
             api: ApiConfig {
                 endpoint: "https://intranet.local/upload.php".to_string(),
@@ -220,19 +1077,62 @@ impl Default for Config {
                 bearer_token: String::new(),
                 basic_username: String::new(),
                 basic_password: String::new(),
+                chunk_size_bytes: default_chunk_size_bytes(),
+                include_file_metadata: false,
+                json_size_key: default_json_size_key(),
+                json_modified_key: default_json_modified_key(),
+                fallback_endpoint: None,
+                json_template: None,
+                response_schema: None,
+                override_filename: None,
+                upload_concurrency: default_upload_concurrency(),
+                sanitize_filename: false,
+                filename_allowed_chars: default_filename_allowed_chars(),
+                follow_redirects: false,
+                attempt_timeout_secs: default_attempt_timeout_secs(),
+                total_timeout_secs: None,
+                json_encoding: default_json_encoding(),
+                pool_idle_timeout_secs: default_pool_idle_timeout_secs(),
+                pool_max_idle_per_host: default_pool_max_idle_per_host(),
+                bearer_token_command: None,
+                bearer_token_refresh: false,
+                max_body_bytes: None,
+                body_limit_action: default_body_limit_action(),
+                if_match: None,
+                if_none_match: None,
+                file_mime: None,
+                correlation_header: default_correlation_header(),
+                sink_dir: String::new(),
+                cleanup_on_conflict: false,
+                cleanup_endpoint: String::new(),
+                http_version: default_http_version(),
+                min_tls_version: None,
             },
             retry: RetryConfig {
                 max_attempts: 3,
                 initial_backoff_secs: 3,
+                idempotent_only: true,
+                circuit_break_after: None,
+                max_retry_after_secs: default_max_retry_after_secs(),
             },
             loop_config: LoopConfig {
                 interval_seconds: 300,
                 allow_nested: false,
+                cron: None,
+                fixed_rate: false,
+                startup_splay_secs: None,
+                resume_interval: false,
+                heartbeat_secs: None,
+                pause_file: None,
             },
             archive: ArchiveConfig {
                 enabled: false,
                 path: "C:\\sap\\archive".to_string(),
                 append_timestamp: true,
+                on_failure: default_on_failure(),
+                quarantine_path: "C:\\sap\\quarantine".to_string(),
+                settle_ms: 0,
+                dry_run: false,
             },
             lookup: LookupConfig {
                 enabled: false,
@@ -241,7 +1141,134 @@ impl Default for Config {
                 cookie: String::new(),
                 timeout_secs: 30,
                 post_url: "http://api.example.com:8080/blah/yadda.php".to_string(),
+                preflight: false,
+                mark_unmatched: false,
+                unmatched_sentinel: default_unmatched_sentinel(),
+                tsv_quote: default_tsv_quote(),
+                output_file: None,
+                post: true,
+                array_key_fields: default_array_key_fields(),
+                key_normalize: Vec::new(),
+                read_auth: None,
+                post_auth: None,
+                fail_on_empty: false,
+                field_columns: HashMap::new(),
+                cookies: HashMap::new(),
+                post_chunk_size: 0,
+                chunk_delay_ms: 0,
+                chunk_jitter_ms: 0,
+                progress_path: None,
+                split_parts_delimiter: None,
+                detect_login_html: false,
+                post_compress: false,
+                post_max_attempts: default_post_max_attempts(),
+                post_format: default_post_format(),
+                log_redact_fields: Vec::new(),
+                http_version: default_http_version(),
+                min_tls_version: None,
             },
+            summary: SummaryConfig::default(),
         }
     }
 }
+
+/// Recursively overlays `overlay` onto `base`; tables merge key by key, anything else replaces.
+fn merge_toml(base: &mut TomlValue, overlay: TomlValue) {
+    match (base, overlay) {
+        (TomlValue::Table(base_table), TomlValue::Table(overlay_table)) => {
+            for (key, value) in overlay_table {
+                match base_table.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base_table.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_with_env_overlay() {
+        let temp_dir = tempdir().unwrap();
+        let base = temp_dir.path().join("config.toml");
+        let overlay = temp_dir.path().join("config.prod.toml");
+
+        let mut base_value = TomlValue::try_from(Config::default()).unwrap();
+        base_value["api"]["extra_fields"] = toml::toml! { site = "PLANT01" }.into();
+        std::fs::write(&base, toml::to_string(&base_value).unwrap()).unwrap();
+        std::fs::write(
+            &overlay,
+            "[api]\nendpoint = \"https://prod.example.com/upload\"\n[api.extra_fields]\ntenant = \"A\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&base, Some("prod")).unwrap();
+        assert_eq!(config.api.endpoint, "https://prod.example.com/upload");
+        assert_eq!(config.api.extra_fields["site"], "PLANT01");
+        assert_eq!(config.api.extra_fields["tenant"], "A");
+        assert_eq!(config.api.mode, Config::default().api.mode);
+
+        assert!(Config::load(&base, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_loop_cron_schedule() {
+        let mut loop_config = Config::default().loop_config;
+        loop_config.interval_seconds = 0;
+        assert!(!loop_config.is_looping());
+        assert!(loop_config.schedule().unwrap().is_none());
+
+        loop_config.cron = Some("0 0 6,14 * * Mon-Fri".to_string());
+        assert!(loop_config.is_looping());
+        let schedule = loop_config.schedule().unwrap().unwrap();
+        let next = schedule.upcoming(chrono::Local).next().unwrap();
+        assert!([6, 14].contains(&chrono::Timelike::hour(&next)));
+
+        // A five-field crontab line has no seconds field
+        loop_config.cron = Some("0 6 * * 1-5".to_string());
+        let error = loop_config.schedule().unwrap_err().to_string();
+        assert!(
+            error.contains("Invalid loop_config.cron expression"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_include_file_metadata_requires_flat_payload() {
+        let mut config = Config::default();
+        config.api.mode = "json_base64".to_string();
+        config.api.include_file_metadata = true;
+        config.validate().unwrap();
+
+        config.api.json_template = Some(r#"{"size":"{{size}}"}"#.to_string());
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("use {{size}} and {{modified}}"), "{}", error);
+    }
+
+    #[test]
+    fn test_extra_field_placeholders_need_regex_groups() {
+        let mut config = Config::default();
+        config
+            .api
+            .extra_fields
+            .insert("note".to_string(), "{literal}".to_string());
+        config.validate().unwrap();
+
+        config.files.glob_is_regex = true;
+        config.files.file_glob = r"\d{14}_y_(?P<plant>\d{3})\.txt".to_string();
+        assert!(config.validate().is_err());
+        config
+            .api
+            .extra_fields
+            .insert("note".to_string(), "{plant}".to_string());
+        config.validate().unwrap();
+    }
+}
@@ -1,28 +1,121 @@
 use anyhow::{Context, Result};
 use chrono::Utc;
+use flate2::read::GzDecoder;
 use glob::glob;
 use log::{debug, info, warn};
+use regex::Regex;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use tokio::fs;
 use tokio::time::{sleep, Duration};
+use walkdir::WalkDir;
 
 use crate::config::{ArchiveConfig, FilesConfig};
+use crate::state::{self, StateStore};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Name prefix of the temporary folders `extraction.capture_stdout_as_input` writes to
+pub const CAPTURE_DIR_PREFIX: &str = ".extractor_stdout";
+
+/// Reads an input file, transparently decompressing it when it is gzipped.
+pub async fn read_input_bytes(path: &Path) -> Result<Vec<u8>> {
+    let bytes = fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+
+    let is_gzip = bytes.starts_with(&GZIP_MAGIC)
+        || path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gz"));
+    if !is_gzip {
+        return Ok(bytes);
+    }
+
+    debug!("Decompressing gzipped input: {}", path.display());
+    let mut decoded = Vec::new();
+    GzDecoder::new(bytes.as_slice())
+        .read_to_end(&mut decoded)
+        .with_context(|| format!("Failed to decompress gzip file: {}", path.display()))?;
+    Ok(decoded)
+}
+
+/// Expands `{year}`, `{month}`, and `{day}` (zero-padded) from `time`.
+pub fn expand_date_tokens(template: &str, time: chrono::DateTime<Utc>) -> String {
+    template
+        .replace("{year}", &time.format("%Y").to_string())
+        .replace("{month}", &time.format("%m").to_string())
+        .replace("{day}", &time.format("%d").to_string())
+}
 
 pub struct FileWatcher {
     config: FilesConfig,
     archive_config: ArchiveConfig,
+    state: Option<StateStore>,
+    /// Compiled `file_glob` when `glob_is_regex` is set
+    name_regex: Option<Regex>,
+    excludes: Vec<NamePattern>,
+}
+
+/// A file-name pattern from the config: a glob, or a regex with `glob_is_regex`
+enum NamePattern {
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl NamePattern {
+    fn new(pattern: &str, is_regex: bool, field: &str) -> Result<Self> {
+        if is_regex {
+            anchored_regex(pattern, field).map(NamePattern::Regex)
+        } else {
+            glob::Pattern::new(pattern)
+                .map(NamePattern::Glob)
+                .with_context(|| format!("{} is not a valid glob: {}", field, pattern))
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Glob(pattern) => pattern.matches(name),
+            NamePattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Compiles `pattern` to match a whole file name.
+fn anchored_regex(pattern: &str, field: &str) -> Result<Regex> {
+    Regex::new(&format!("^(?:{})$", pattern))
+        .with_context(|| format!("{} is not a valid regex: {}", field, pattern))
 }
 
 impl FileWatcher {
     pub fn new(files_config: &FilesConfig) -> Result<Self> {
+        let name_regex = if files_config.glob_is_regex {
+            Some(anchored_regex(&files_config.file_glob, "files.file_glob")?)
+        } else {
+            None
+        };
+        let excludes = files_config
+            .exclude_glob
+            .iter()
+            .map(|pattern| {
+                NamePattern::new(pattern, files_config.glob_is_regex, "files.exclude_glob")
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         Ok(Self {
             config: files_config.clone(),
             archive_config: ArchiveConfig {
                 enabled: false,
                 path: String::new(),
                 append_timestamp: false,
+                on_failure: "leave".to_string(),
+                quarantine_path: String::new(),
+                settle_ms: 0,
+                dry_run: false,
             },
+            state: None,
+            name_regex,
+            excludes,
         })
     }
 
@@ -31,37 +124,58 @@ impl FileWatcher {
         self
     }
 
-    pub async fn find_newest_file(&self) -> Result<Option<PathBuf>> {
-        let pattern = format!("{}/{}", self.config.output_dir, self.config.file_glob);
-        debug!("Searching for files matching pattern: {}", pattern);
-
-        let mut candidates = Vec::new();
+    /// Loads `files.state_path` when a feature needs state kept between runs;
+    /// `track_runs` is set for `loop.resume_interval`.
+    pub fn with_state(mut self, track_runs: bool) -> Result<Self> {
+        if self.config.skip_if_unchanged || track_runs {
+            self.state = Some(StateStore::open(&self.config.state_path)?.with_retention(
+                self.config.state_max_entries,
+                self.config.state_max_age_days,
+            ));
+        }
+        Ok(self)
+    }
 
-        for entry in glob(&pattern).context("Failed to read glob pattern")? {
-            match entry {
-                Ok(path) => {
-                    if path.is_file() {
-                        debug!("Found candidate file: {}", path.display());
-                        candidates.push(path);
-                    }
-                }
-                Err(e) => {
-                    warn!("Error reading directory entry: {}", e);
-                }
-            }
+    /// Content hash of `path`, or `None` when no state is kept.
+    pub async fn tracked_hash(&self, path: &Path) -> Result<Option<String>> {
+        match &self.state {
+            Some(_) => Ok(Some(state::content_hash(path).await?)),
+            None => Ok(None),
         }
+    }
 
-        if candidates.is_empty() {
-            return Ok(None);
+    /// Whether `hash` matches the last successfully processed file.
+    pub fn is_unchanged(&self, hash: &str) -> bool {
+        self.config.skip_if_unchanged
+            && self
+                .state
+                .as_ref()
+                .and_then(|state| state.last_hash())
+                .is_some_and(|last| last == hash)
+    }
+
+    /// When the last successful run finished, if state is kept and one was recorded.
+    pub fn last_run_success(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.state.as_ref().and_then(|state| state.last_success_at())
+    }
+
+    pub fn record_run_success(&self) -> Result<()> {
+        match &self.state {
+            Some(state) => state.record_success(chrono::Utc::now()),
+            None => Ok(()),
         }
+    }
 
-        // Sort by modification time, with timestamp prefix as tiebreaker
-        candidates.sort_by(|a, b| {
-            let a_time = self.get_file_time(a).unwrap_or(SystemTime::UNIX_EPOCH);
-            let b_time = self.get_file_time(b).unwrap_or(SystemTime::UNIX_EPOCH);
-            b_time.cmp(&a_time) // Reverse order (newest first)
-        });
+    pub fn record_processed(&self, path: &Path, hash: String) -> Result<()> {
+        match &self.state {
+            Some(state) => state.record_processed(path, hash),
+            None => Ok(()),
+        }
+    }
 
+    pub async fn find_newest_file(&self) -> Result<Option<PathBuf>> {
+        let mut candidates = self.candidates()?;
+        self.sort_files(&mut candidates, "newest_first");
         let newest = candidates.into_iter().next();
         if let Some(ref path) = newest {
             info!("Selected newest file: {} (mtime: {:?})", 
@@ -72,6 +186,259 @@ impl FileWatcher {
         Ok(newest)
     }
 
+    /// Fails with an actionable message when the output directory is missing or not a directory.
+    pub fn check_output_dir(&self) -> Result<()> {
+        let output_dir = Path::new(&self.config.output_dir);
+        if !output_dir.exists() {
+            anyhow::bail!(
+                "Output directory does not exist: {}\nPlease check your configuration or run the extraction first.",
+                output_dir.display()
+            );
+        }
+
+        if !output_dir.is_dir() {
+            anyhow::bail!(
+                "Output path is not a directory: {}\nPlease check your configuration.",
+                output_dir.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The output directory is escaped so brackets or asterisks in its name match literally.
+    pub fn glob_pattern(&self) -> String {
+        let output_dir = glob::Pattern::escape(&self.config.output_dir);
+        if self.config.recursive {
+            format!("{}/**/{}", output_dir, self.config.file_glob)
+        } else {
+            format!("{}/{}", output_dir, self.config.file_glob)
+        }
+    }
+
+    /// Archive and quarantine folders may live under output_dir; a recursive
+    /// search must not pick their files up again. Nor a stdout capture left
+    /// behind by an interrupted run.
+    fn is_excluded_dir(&self, path: &Path) -> bool {
+        if !self.config.recursive {
+            return false;
+        }
+        let in_capture_dir = path.components().any(|component| {
+            component
+                .as_os_str()
+                .to_str()
+                .is_some_and(|name| name.starts_with(CAPTURE_DIR_PREFIX))
+        });
+        if in_capture_dir {
+            return true;
+        }
+        let mut dirs = vec![self.archive_config.quarantine_path.as_str()];
+        if self.archive_config.enabled {
+            // A dated archive path excludes everything below its fixed part
+            let path = &self.archive_config.path;
+            dirs.push(path.find('{').map_or(path.as_str(), |i| &path[..i]));
+        }
+        dirs.into_iter()
+            .filter(|dir| !dir.is_empty())
+            .any(|dir| path.starts_with(dir))
+    }
+
+    fn is_excluded_name(&self, path: &Path) -> bool {
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => return false,
+        };
+        self.excludes.iter().any(|pattern| pattern.matches(name))
+    }
+
+    /// Timestamp parsed from the filename prefix, when prefix mode is on and it parses.
+    pub fn filename_timestamp(&self, path: &Path) -> Option<SystemTime> {
+        if !self.config.filename_timestamp_prefix {
+            return None;
+        }
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|filename| self.parse_timestamp_from_filename(filename))
+    }
+
+    /// Claims a file by renaming it with `files.in_progress_suffix`; returns the
+    /// path to work from. Without a suffix the file is used where it is.
+    pub async fn acquire_lease(&self, path: &Path) -> Result<PathBuf> {
+        let Some(suffix) = &self.config.in_progress_suffix else {
+            return Ok(path.to_path_buf());
+        };
+
+        let mut leased = path.as_os_str().to_owned();
+        leased.push(suffix);
+        let leased = PathBuf::from(leased);
+        match fs::rename(path, &leased).await {
+            Ok(()) => {
+                debug!("Claimed {} as {}", path.display(), leased.display());
+                Ok(leased)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => anyhow::bail!(
+                "File was claimed by another cycle or removed: {}",
+                path.display()
+            ),
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to claim file: {}", path.display())),
+        }
+    }
+
+    /// Renames a leased file back if it was not archived or quarantined.
+    pub async fn release_lease(&self, leased: &Path) -> Result<()> {
+        let original = self.unleased_path(leased);
+        if original == leased || !leased.exists() {
+            return Ok(());
+        }
+
+        fs::rename(leased, &original).await.with_context(|| {
+            format!("Failed to rename {} back to {}", leased.display(), original.display())
+        })?;
+        debug!("Released {}", original.display());
+        Ok(())
+    }
+
+    /// The file's real path, without the in-progress suffix.
+    pub fn unleased_path(&self, path: &Path) -> PathBuf {
+        let stripped = self.config.in_progress_suffix.as_deref().and_then(|suffix| {
+            path.to_str()
+                .and_then(|p| p.strip_suffix(suffix))
+                .map(PathBuf::from)
+        });
+        stripped.unwrap_or_else(|| path.to_path_buf())
+    }
+
+    fn is_leased(&self, path: &Path) -> bool {
+        self.unleased_path(path) != path
+    }
+
+    /// Every candidate, in `files.process_order`.
+    pub async fn find_all_files(&self) -> Result<Vec<PathBuf>> {
+        let mut candidates = self.candidates()?;
+        self.sort_files(&mut candidates, &self.config.process_order);
+        Ok(candidates)
+    }
+
+    /// Named groups of a regex `file_glob` matched against a file name, for the
+    /// `{name}` placeholders in `api.extra_fields`. Empty in glob mode.
+    pub fn name_captures(&self, file_name: &str) -> Vec<(String, String)> {
+        let Some(captures) = self.name_regex.as_ref().and_then(|regex| regex.captures(file_name))
+        else {
+            return Vec::new();
+        };
+        self.name_regex
+            .iter()
+            .flat_map(|regex| regex.capture_names().flatten())
+            .filter_map(|name| Some((name.to_string(), captures.name(name)?.as_str().to_string())))
+            .collect()
+    }
+
+    /// Sorts by file time ("newest_first", "oldest_first"), with the timestamp
+    /// prefix taking precedence over mtime, or by file name ("name_asc").
+    pub fn sort_files(&self, files: &mut [PathBuf], order: &str) {
+        // Each file's time is read once, which matters for large recursive trees
+        let file_time = |path: &PathBuf| self.get_file_time(path).unwrap_or(SystemTime::UNIX_EPOCH);
+        match order {
+            "oldest_first" => files.sort_by_cached_key(file_time),
+            "name_asc" => files.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then(a.cmp(b))),
+            _ => files.sort_by_cached_key(|path| std::cmp::Reverse(file_time(path))),
+        }
+    }
+
+    /// Matching files that may be selected, unsorted.
+    fn candidates(&self) -> Result<Vec<PathBuf>> {
+        self.check_output_dir()?;
+
+        let mut candidates = Vec::new();
+
+        for path in self.matching_paths()? {
+            if path.is_file() {
+                if self.is_leased(&path) {
+                    debug!("Skipping file claimed by another cycle: {}", path.display());
+                    continue;
+                }
+                if self.is_excluded_dir(&path) {
+                    debug!("Skipping archived file: {}", path.display());
+                    continue;
+                }
+                if self.is_excluded_name(&path) {
+                    debug!("Skipping excluded file: {}", path.display());
+                    continue;
+                }
+                if self.has_future_mtime(&path) && self.config.ignore_future_mtime {
+                    warn!("Ignoring file with future mtime: {}", path.display());
+                    continue;
+                }
+                debug!("Found candidate file: {}", path.display());
+                candidates.push(path);
+            }
+        }
+
+        Ok(candidates)
+    }
+
+    /// Paths matching `file_glob`, through the glob crate or, in regex mode, by
+    /// listing output_dir and matching each file name.
+    fn matching_paths(&self) -> Result<Vec<PathBuf>> {
+        let regex = match &self.name_regex {
+            Some(regex) => regex,
+            None => {
+                let pattern = self.glob_pattern();
+                debug!("Searching for files matching pattern: {}", pattern);
+
+                let mut paths = Vec::new();
+                for entry in glob(&pattern).context("Failed to read glob pattern")? {
+                    match entry {
+                        Ok(path) => paths.push(path),
+                        Err(e) => warn!("Error reading directory entry: {}", e),
+                    }
+                }
+                return Ok(paths);
+            }
+        };
+
+        debug!(
+            "Searching {} for file names matching regex: {}",
+            self.config.output_dir,
+            regex.as_str()
+        );
+        let max_depth = if self.config.recursive { usize::MAX } else { 1 };
+        let mut paths = Vec::new();
+        for entry in WalkDir::new(&self.config.output_dir).min_depth(1).max_depth(max_depth) {
+            match entry {
+                Ok(entry) => {
+                    let name = entry.file_name().to_str();
+                    if name.is_some_and(|name| regex.is_match(name)) {
+                        paths.push(entry.into_path());
+                    }
+                }
+                Err(e) => warn!("Error reading directory entry: {}", e),
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Warns when the file's mtime is ahead of the local clock by more than the tolerance.
+    fn has_future_mtime(&self, path: &Path) -> bool {
+        let mtime = match std::fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(mtime) => mtime,
+            Err(_) => return false,
+        };
+
+        match mtime.duration_since(SystemTime::now()) {
+            Ok(ahead) if ahead.as_secs() > self.config.future_mtime_tolerance_secs => {
+                warn!(
+                    "File {} has an mtime {}s in the future; check for clock skew on the share",
+                    path.display(),
+                    ahead.as_secs()
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn get_file_time(&self, path: &Path) -> Result<SystemTime> {
         let metadata = std::fs::metadata(path)?;
         let mtime = metadata.modified()?;
@@ -121,10 +488,12 @@ impl FileWatcher {
         let required_stable_checks = (self.config.stable_size_check_secs * 2).max(1); // Check every 0.5 seconds
         let max_wait_secs = 10;
         let mut total_wait_secs = 0;
+        let mut not_found_count = 0;
 
         loop {
             match fs::metadata(file_path).await {
                 Ok(metadata) => {
+                    not_found_count = 0;
                     let current_size = metadata.len();
                     debug!("File size check: {} bytes (was {} bytes)", current_size, last_size);
                     
@@ -139,6 +508,21 @@ impl FileWatcher {
                         last_size = current_size;
                     }
                 }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    not_found_count += 1;
+                    if not_found_count >= self.config.stable_missing_retries {
+                        anyhow::bail!(
+                            "File disappeared while waiting for it to stabilize: {}",
+                            file_path.display()
+                        );
+                    }
+                    warn!(
+                        "File not found ({}/{}): {}",
+                        not_found_count,
+                        self.config.stable_missing_retries,
+                        file_path.display()
+                    );
+                }
                 Err(e) => {
                     warn!("Error checking file size: {}", e);
                 }
@@ -154,16 +538,161 @@ impl FileWatcher {
         }
     }
 
-    pub async fn archive_file(&self, file_path: &Path) -> Result<()> {
+    /// Refuses files larger than `max_size_bytes`, e.g. a runaway extraction.
+    pub async fn check_size_limit(&self, file_path: &Path) -> Result<()> {
+        let max_size = match self.config.max_size_bytes {
+            Some(max_size) => max_size,
+            None => return Ok(()),
+        };
+
+        let size = fs::metadata(file_path)
+            .await
+            .with_context(|| format!("Failed to read file metadata: {}", file_path.display()))?
+            .len();
+        if size > max_size {
+            anyhow::bail!(
+                "File {} is {} bytes, over files.max_size_bytes ({}); refusing to upload",
+                file_path.display(),
+                size,
+                max_size
+            );
+        }
+
+        Ok(())
+    }
+
+    pub async fn verify_row_count(&self, file_path: &Path, actual: usize) -> Result<()> {
+        if !self.config.verify_count_sidecar {
+            return Ok(());
+        }
+
+        let mut sidecar = self.unleased_path(file_path).into_os_string();
+        sidecar.push(".count");
+        let sidecar = PathBuf::from(sidecar);
+
+        let problem = match fs::read_to_string(&sidecar).await {
+            Ok(content) => match content.trim().parse::<usize>() {
+                Ok(expected) if expected == actual => {
+                    info!("Row count matches sidecar: {} rows", actual);
+                    return Ok(());
+                }
+                Ok(expected) => format!(
+                    "Row count mismatch for {}: sidecar expects {}, found {}",
+                    file_path.display(),
+                    expected,
+                    actual
+                ),
+                Err(_) => format!(
+                    "Count sidecar {} does not contain a number: '{}'",
+                    sidecar.display(),
+                    content.trim()
+                ),
+            },
+            Err(e) => format!("Failed to read count sidecar {}: {}", sidecar.display(), e),
+        };
+
+        if self.config.count_mismatch == "warn" {
+            warn!("{}", problem);
+            Ok(())
+        } else {
+            anyhow::bail!(problem)
+        }
+    }
+
+    /// Returns the archived path, or `None` when archiving is disabled.
+    pub async fn archive_file(&self, file_path: &Path) -> Result<Option<PathBuf>> {
         if !self.archive_config.enabled {
+            return Ok(None);
+        }
+
+        // Dated folders follow the file's own timestamp, falling back to now
+        let file_time = self.get_file_time(file_path).unwrap_or_else(|_| SystemTime::now());
+        let archive_dir = expand_date_tokens(&self.archive_config.path, file_time.into());
+        let archive_path = self
+            .move_file_to(file_path, Path::new(&archive_dir))
+            .await?;
+        if !self.archive_config.dry_run {
+            info!("File archived to: {}", archive_path.display());
+        }
+        Ok(Some(archive_path))
+    }
+
+    /// Fails fast when an enabled archive (or the quarantine folder, when used)
+    /// cannot be written, instead of after a successful upload.
+    pub fn check_archive_writable(&self) -> Result<()> {
+        if self.archive_config.dry_run {
+            info!("archive.dry_run is set; files will not be moved");
             return Ok(());
         }
 
+        let mut dirs = Vec::new();
+        if self.archive_config.enabled {
+            // Dated folders are created later; check their fixed parent
+            let path = &self.archive_config.path;
+            dirs.push(("archive.path", path.find('{').map_or(path.as_str(), |i| &path[..i])));
+        }
+        if self.archive_config.on_failure == "quarantine" {
+            dirs.push(("archive.quarantine_path", self.archive_config.quarantine_path.as_str()));
+        }
+
+        for (name, dir) in dirs {
+            let dir = Path::new(dir);
+            std::fs::create_dir_all(dir)
+                .and_then(|_| tempfile::NamedTempFile::new_in(dir))
+                .with_context(|| {
+                    format!(
+                        "{} is not writable: {}\nCheck that the folder exists, the share is mounted, and this account can write to it.",
+                        name,
+                        dir.display()
+                    )
+                })?;
+            debug!("{} is writable: {}", name, dir.display());
+        }
+        Ok(())
+    }
+
+    pub async fn quarantine_file(&self, file_path: &Path) -> Result<PathBuf> {
+        let quarantine_path = self
+            .move_file_to(file_path, Path::new(&self.archive_config.quarantine_path))
+            .await?;
+        if !self.archive_config.dry_run {
+            warn!("File quarantined to: {}", quarantine_path.display());
+        }
+        Ok(quarantine_path)
+    }
+
+    /// Polls until a moved file stops showing up at its old path, so the next
+    /// cycle's glob does not select it again. Bounded by `archive.settle_ms`.
+    async fn wait_until_gone(&self, path: &Path) {
+        let settle = Duration::from_millis(self.archive_config.settle_ms);
+        if settle.is_zero() {
+            return;
+        }
+
+        let started = tokio::time::Instant::now();
+        while fs::metadata(path).await.is_ok() {
+            if started.elapsed() >= settle {
+                warn!(
+                    "{} is still listed {} ms after it was moved",
+                    path.display(),
+                    self.archive_config.settle_ms
+                );
+                return;
+            }
+            sleep(Duration::from_millis(50).min(settle)).await;
+        }
+        debug!("Move settled after {:?}: {}", started.elapsed(), path.display());
+    }
+
+    async fn move_file_to(&self, file_path: &Path, dir: &Path) -> Result<PathBuf> {
+        // A leased file keeps its real name at the destination
+        let source_path = file_path;
+        let file_path = &self.unleased_path(source_path);
         let filename = file_path.file_name()
             .context("File has no filename")?
             .to_string_lossy();
 
-        let mut archive_filename = filename.to_string();
+        let mut target_filename = filename.to_string();
         
         if self.archive_config.append_timestamp {
             let now = Utc::now();
@@ -176,23 +705,28 @@ impl FileWatcher {
                 .map(|s| format!(".{}", s))
                 .unwrap_or_default();
             
-            archive_filename = format!("{}_{}{}", stem, timestamp, extension);
+            target_filename = format!("{}_{}{}", stem, timestamp, extension);
         }
 
-        let archive_path = Path::new(&self.archive_config.path).join(&archive_filename);
-        
-        // Create archive directory if it doesn't exist
-        if let Some(parent) = archive_path.parent() {
+        let target_path = dir.join(&target_filename);
+
+        if self.archive_config.dry_run {
+            info!("Dry run: would move {} to {}", source_path.display(), target_path.display());
+            return Ok(target_path);
+        }
+
+        // Create target directory if it doesn't exist
+        if let Some(parent) = target_path.parent() {
             fs::create_dir_all(parent).await
-                .with_context(|| format!("Failed to create archive directory: {}", parent.display()))?;
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
         }
 
-        // Move file to archive
-        fs::rename(file_path, &archive_path).await
-            .with_context(|| format!("Failed to move file from {} to {}", file_path.display(), archive_path.display()))?;
+        // Move file to target
+        fs::rename(source_path, &target_path).await
+            .with_context(|| format!("Failed to move file from {} to {}", source_path.display(), target_path.display()))?;
+        self.wait_until_gone(source_path).await;
 
-        info!("File archived to: {}", archive_path.display());
-        Ok(())
+        Ok(target_path)
     }
 }
 
@@ -200,18 +734,40 @@ impl FileWatcher {
 mod tests {
     use super::*;
     use std::fs::File;
-    
+    use std::io::Write;
+
     use tempfile::tempdir;
 
-    #[tokio::test]
-    async fn test_find_newest_file() {
-        let temp_dir = tempdir().unwrap();
-        let files_config = FilesConfig {
-            output_dir: temp_dir.path().to_string_lossy().to_string(),
+    fn test_files_config(dir: &Path) -> FilesConfig {
+        FilesConfig {
+            output_dir: dir.to_string_lossy().to_string(),
             file_glob: "*.txt".to_string(),
             filename_timestamp_prefix: false,
             stable_size_check_secs: 1,
-        };
+            verify_count_sidecar: false,
+            count_mismatch: "fail".to_string(),
+            process_all: false,
+            merge_matching: false,
+            max_size_bytes: None,
+            future_mtime_tolerance_secs: 300,
+            ignore_future_mtime: false,
+            stable_missing_retries: 3,
+            recursive: false,
+            in_progress_suffix: None,
+            skip_if_unchanged: false,
+            state_path: String::new(),
+            state_max_entries: 0,
+            state_max_age_days: 0,
+            glob_is_regex: false,
+            exclude_glob: Vec::new(),
+            process_order: "newest_first".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_find_newest_file() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = test_files_config(temp_dir.path());
 
         let watcher = FileWatcher::new(&files_config).unwrap();
 
@@ -232,10 +788,8 @@ mod tests {
     async fn test_timestamp_parsing() {
         let temp_dir = tempdir().unwrap();
         let files_config = FilesConfig {
-            output_dir: temp_dir.path().to_string_lossy().to_string(),
-            file_glob: "*.txt".to_string(),
             filename_timestamp_prefix: true,
-            stable_size_check_secs: 1,
+            ..test_files_config(temp_dir.path())
         };
 
         let watcher = FileWatcher::new(&files_config).unwrap();
@@ -251,4 +805,435 @@ mod tests {
         assert!(newest.is_some());
         assert_eq!(newest.unwrap().file_name().unwrap(), "20251016170602_y_149-ALL.txt");
     }
+
+    #[tokio::test]
+    async fn test_find_all_files_newest_first() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = FilesConfig {
+            filename_timestamp_prefix: true,
+            process_all: true,
+            ..test_files_config(temp_dir.path())
+        };
+
+        let watcher = FileWatcher::new(&files_config).unwrap();
+
+        for name in ["20251016170602_y_149-ALL.txt", "20251016170601_y_149-ALL.txt", "20251016170603_y_149-ALL.txt"] {
+            File::create(temp_dir.path().join(name)).unwrap();
+        }
+
+        let files = watcher.find_all_files().await.unwrap();
+        let names: Vec<_> = files.iter().map(|f| f.file_name().unwrap().to_string_lossy().to_string()).collect();
+        assert_eq!(names, vec!["20251016170603_y_149-ALL.txt", "20251016170602_y_149-ALL.txt", "20251016170601_y_149-ALL.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_process_order() {
+        let temp_dir = tempdir().unwrap();
+        let mut files_config = FilesConfig {
+            process_all: true,
+            process_order: "oldest_first".to_string(),
+            ..test_files_config(temp_dir.path())
+        };
+
+        // Name order differs from mtime order
+        let now = SystemTime::now();
+        for (name, age_secs) in [("b.txt", 300), ("c.txt", 100), ("a.txt", 200)] {
+            let file = File::create(temp_dir.path().join(name)).unwrap();
+            file.set_modified(now - std::time::Duration::from_secs(age_secs)).unwrap();
+        }
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files.iter().map(|f| f.file_name().unwrap().to_string_lossy().to_string()).collect()
+        };
+
+        let watcher = FileWatcher::new(&files_config).unwrap();
+        assert_eq!(names(watcher.find_all_files().await.unwrap()), vec!["b.txt", "a.txt", "c.txt"]);
+        // The single-file run still picks the newest
+        let newest = watcher.find_newest_file().await.unwrap().unwrap();
+        assert_eq!(newest.file_name().unwrap(), "c.txt");
+
+        files_config.process_order = "name_asc".to_string();
+        let watcher = FileWatcher::new(&files_config).unwrap();
+        assert_eq!(names(watcher.find_all_files().await.unwrap()), vec!["a.txt", "b.txt", "c.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_size_limit() {
+        let temp_dir = tempdir().unwrap();
+        let mut files_config = FilesConfig {
+            max_size_bytes: Some(4),
+            ..test_files_config(temp_dir.path())
+        };
+
+        let small = temp_dir.path().join("small.txt");
+        let large = temp_dir.path().join("large.txt");
+        std::fs::write(&small, b"1234").unwrap();
+        std::fs::write(&large, b"12345").unwrap();
+
+        let watcher = FileWatcher::new(&files_config).unwrap();
+        assert!(watcher.check_size_limit(&small).await.is_ok());
+        assert!(watcher.check_size_limit(&large).await.is_err());
+
+        files_config.max_size_bytes = None;
+        let watcher = FileWatcher::new(&files_config).unwrap();
+        assert!(watcher.check_size_limit(&large).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_missing_output_dir_is_error() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = test_files_config(&temp_dir.path().join("missing"));
+
+        let watcher = FileWatcher::new(&files_config).unwrap();
+        let err = watcher.find_newest_file().await.unwrap_err();
+        assert!(err.to_string().contains("Output directory does not exist"));
+    }
+
+    #[tokio::test]
+    async fn test_read_input_bytes_gzip() {
+        let temp_dir = tempdir().unwrap();
+        let plain = temp_dir.path().join("plain.txt");
+        let gzipped = temp_dir.path().join("packed.txt.gz");
+
+        std::fs::write(&plain, b"a\tb\n").unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"a\tb\n").unwrap();
+        std::fs::write(&gzipped, encoder.finish().unwrap()).unwrap();
+
+        assert_eq!(read_input_bytes(&plain).await.unwrap(), b"a\tb\n");
+        assert_eq!(read_input_bytes(&gzipped).await.unwrap(), b"a\tb\n");
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_stable_file_missing() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = FilesConfig {
+            stable_missing_retries: 2,
+            ..test_files_config(temp_dir.path())
+        };
+
+        let watcher = FileWatcher::new(&files_config).unwrap();
+        let result = watcher
+            .wait_for_stable_file(&temp_dir.path().join("gone.txt"))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ignore_future_mtime() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = FilesConfig {
+            ignore_future_mtime: true,
+            ..test_files_config(temp_dir.path())
+        };
+
+        let watcher = FileWatcher::new(&files_config).unwrap();
+
+        let skewed = File::create(temp_dir.path().join("skewed.txt")).unwrap();
+        skewed.set_modified(SystemTime::now() + std::time::Duration::from_secs(3600)).unwrap();
+        File::create(temp_dir.path().join("current.txt")).unwrap();
+
+        let newest = watcher.find_newest_file().await.unwrap();
+        assert_eq!(newest.unwrap().file_name().unwrap(), "current.txt");
+    }
+
+    #[tokio::test]
+    async fn test_verify_row_count_sidecar() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = FilesConfig {
+            verify_count_sidecar: true,
+            ..test_files_config(temp_dir.path())
+        };
+
+        let watcher = FileWatcher::new(&files_config).unwrap();
+
+        let file = temp_dir.path().join("report.txt");
+        File::create(&file).unwrap();
+        std::fs::write(temp_dir.path().join("report.txt.count"), "3\n").unwrap();
+
+        assert!(watcher.verify_row_count(&file, 3).await.is_ok());
+        assert!(watcher.verify_row_count(&file, 2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_recursive_search() {
+        let temp_dir = tempdir().unwrap();
+        let mut files_config = test_files_config(temp_dir.path());
+        let archive_dir = temp_dir.path().join("archive");
+        let archive_config = ArchiveConfig {
+            enabled: true,
+            path: archive_dir.to_string_lossy().to_string(),
+            append_timestamp: false,
+            on_failure: "leave".to_string(),
+            quarantine_path: String::new(),
+            settle_ms: 0,
+            dry_run: false,
+        };
+
+        std::fs::create_dir_all(temp_dir.path().join("149")).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("250")).unwrap();
+        std::fs::create_dir_all(&archive_dir).unwrap();
+        File::create(temp_dir.path().join("top.txt")).unwrap();
+        File::create(temp_dir.path().join("149").join("a.txt")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        File::create(temp_dir.path().join("250").join("b.txt")).unwrap();
+        File::create(archive_dir.join("old.txt")).unwrap();
+
+        let flat = FileWatcher::new(&files_config)
+            .unwrap()
+            .with_archive(&archive_config);
+        assert_eq!(flat.find_all_files().await.unwrap().len(), 1);
+
+        files_config.recursive = true;
+        let watcher = FileWatcher::new(&files_config)
+            .unwrap()
+            .with_archive(&archive_config);
+        let files = watcher.find_all_files().await.unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0], temp_dir.path().join("250").join("b.txt"));
+        assert!(!files.iter().any(|f| f.starts_with(&archive_dir)));
+    }
+
+    #[tokio::test]
+    async fn test_glob_is_regex() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = FilesConfig {
+            file_glob: r"\d{14}_y_149-ALL\.txt".to_string(),
+            glob_is_regex: true,
+            ..test_files_config(temp_dir.path())
+        };
+
+        File::create(temp_dir.path().join("20250115143022_y_149-ALL.txt")).unwrap();
+        File::create(temp_dir.path().join("2025011514_y_149-ALL.txt")).unwrap();
+        File::create(temp_dir.path().join("20250115143022_y_149-ALL.txt.tmp")).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("149")).unwrap();
+        File::create(temp_dir.path().join("149").join("20250115143023_y_149-ALL.txt")).unwrap();
+
+        let watcher = FileWatcher::new(&files_config).unwrap();
+        let files = watcher.find_all_files().await.unwrap();
+        assert_eq!(files, vec![temp_dir.path().join("20250115143022_y_149-ALL.txt")]);
+        assert!(watcher.name_captures("20250115143022_y_149-ALL.txt").is_empty());
+
+        let named = FilesConfig {
+            file_glob: r"\d{14}_y_(?P<plant>\d{3})-ALL\.txt".to_string(),
+            ..files_config.clone()
+        };
+        let watcher = FileWatcher::new(&named).unwrap();
+        assert_eq!(
+            watcher.name_captures("20250115143022_y_149-ALL.txt"),
+            vec![("plant".to_string(), "149".to_string())]
+        );
+        assert!(watcher.name_captures("report.txt").is_empty());
+
+        let invalid = FilesConfig {
+            file_glob: "(".to_string(),
+            ..files_config
+        };
+        assert!(FileWatcher::new(&invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_exclude_glob() {
+        let temp_dir = tempdir().unwrap();
+        let mut files_config = FilesConfig {
+            file_glob: "report*".to_string(),
+            exclude_glob: vec!["*.tmp".to_string()],
+            ..test_files_config(temp_dir.path())
+        };
+
+        File::create(temp_dir.path().join("report-ALL.txt")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        File::create(temp_dir.path().join("report-ALL.txt.tmp")).unwrap();
+        File::create(temp_dir.path().join("report-TEMP.txt")).unwrap();
+
+        let watcher = FileWatcher::new(&files_config).unwrap();
+        let files = watcher.find_all_files().await.unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0], temp_dir.path().join("report-TEMP.txt"));
+
+        files_config.file_glob = r"report-.*\.txt".to_string();
+        files_config.glob_is_regex = true;
+        files_config.exclude_glob = vec![".*-TEMP.*".to_string()];
+        let watcher = FileWatcher::new(&files_config).unwrap();
+        let newest = watcher.find_newest_file().await.unwrap();
+        assert_eq!(newest, Some(temp_dir.path().join("report-ALL.txt")));
+    }
+
+    #[tokio::test]
+    async fn test_in_progress_lease() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = FilesConfig {
+            file_glob: "*".to_string(),
+            in_progress_suffix: Some(".processing".to_string()),
+            ..test_files_config(temp_dir.path())
+        };
+        let archive_dir = temp_dir.path().join("archive");
+        let archive_config = ArchiveConfig {
+            enabled: true,
+            path: archive_dir.to_string_lossy().to_string(),
+            append_timestamp: false,
+            on_failure: "leave".to_string(),
+            quarantine_path: String::new(),
+            settle_ms: 0,
+            dry_run: false,
+        };
+        let watcher = FileWatcher::new(&files_config)
+            .unwrap()
+            .with_archive(&archive_config);
+
+        let file = temp_dir.path().join("report.txt");
+        File::create(&file).unwrap();
+
+        let leased = watcher.acquire_lease(&file).await.unwrap();
+        assert_eq!(leased, temp_dir.path().join("report.txt.processing"));
+        assert!(!file.exists());
+        // A second cycle neither sees nor claims it
+        assert!(watcher.find_all_files().await.unwrap().is_empty());
+        assert!(watcher.acquire_lease(&file).await.is_err());
+
+        watcher.release_lease(&leased).await.unwrap();
+        assert!(file.exists());
+
+        // Archived under its real name; nothing left to release
+        let leased = watcher.acquire_lease(&file).await.unwrap();
+        let archived = watcher.archive_file(&leased).await.unwrap().unwrap();
+        assert_eq!(archived, archive_dir.join("report.txt"));
+        watcher.release_lease(&leased).await.unwrap();
+        assert!(!file.exists());
+    }
+
+    #[tokio::test]
+    async fn test_archive_path_date_tokens() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = FilesConfig {
+            filename_timestamp_prefix: true,
+            ..test_files_config(temp_dir.path())
+        };
+        let archive_root = temp_dir.path().join("archive");
+        let archive_config = ArchiveConfig {
+            enabled: true,
+            path: format!("{}/{{year}}/{{month}}/{{day}}", archive_root.display()),
+            append_timestamp: false,
+            on_failure: "leave".to_string(),
+            quarantine_path: String::new(),
+            settle_ms: 100,
+            dry_run: false,
+        };
+        let watcher = FileWatcher::new(&files_config)
+            .unwrap()
+            .with_archive(&archive_config);
+
+        let file = temp_dir.path().join("20250107093000_y_149.txt");
+        File::create(&file).unwrap();
+
+        let archived = watcher.archive_file(&file).await.unwrap().unwrap();
+        assert_eq!(
+            archived,
+            archive_root
+                .join("2025")
+                .join("01")
+                .join("07")
+                .join("20250107093000_y_149.txt")
+        );
+        assert!(archived.exists());
+    }
+
+    #[tokio::test]
+    async fn test_archive_dry_run_leaves_file() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = FilesConfig {
+            filename_timestamp_prefix: true,
+            ..test_files_config(temp_dir.path())
+        };
+        let archive_root = temp_dir.path().join("archive");
+        let archive_config = ArchiveConfig {
+            enabled: true,
+            path: format!("{}/{{year}}", archive_root.display()),
+            append_timestamp: false,
+            on_failure: "leave".to_string(),
+            quarantine_path: String::new(),
+            settle_ms: 100,
+            dry_run: true,
+        };
+        let watcher = FileWatcher::new(&files_config)
+            .unwrap()
+            .with_archive(&archive_config);
+        watcher.check_archive_writable().unwrap();
+
+        let file = temp_dir.path().join("20250107093000_y_149.txt");
+        File::create(&file).unwrap();
+
+        let archived = watcher.archive_file(&file).await.unwrap().unwrap();
+        assert_eq!(
+            archived,
+            archive_root.join("2025").join("20250107093000_y_149.txt")
+        );
+        assert!(file.exists());
+        assert!(!archive_root.exists());
+    }
+
+    #[test]
+    fn test_check_archive_writable() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = test_files_config(temp_dir.path());
+        let mut archive_config = ArchiveConfig {
+            enabled: true,
+            path: temp_dir.path().join("archive").to_string_lossy().to_string(),
+            append_timestamp: false,
+            on_failure: "leave".to_string(),
+            quarantine_path: String::new(),
+            settle_ms: 0,
+            dry_run: false,
+        };
+        let watcher = FileWatcher::new(&files_config)
+            .unwrap()
+            .with_archive(&archive_config);
+        assert!(watcher.check_archive_writable().is_ok());
+        // The probe file is removed again
+        let archive_dir = temp_dir.path().join("archive");
+        assert_eq!(std::fs::read_dir(&archive_dir).unwrap().count(), 0);
+
+        // A regular file where the folder should be
+        let blocker = temp_dir.path().join("blocker");
+        File::create(&blocker).unwrap();
+        archive_config.path = blocker.to_string_lossy().to_string();
+        let watcher = FileWatcher::new(&files_config)
+            .unwrap()
+            .with_archive(&archive_config);
+        let err = watcher.check_archive_writable().unwrap_err();
+        assert!(err.to_string().contains("archive.path is not writable"));
+    }
+
+    #[tokio::test]
+    async fn test_quarantine_file() {
+        let temp_dir = tempdir().unwrap();
+        let files_config = test_files_config(temp_dir.path());
+        let quarantine_dir = temp_dir.path().join("quarantine");
+        let archive_config = ArchiveConfig {
+            enabled: false,
+            path: String::new(),
+            append_timestamp: false,
+            on_failure: "quarantine".to_string(),
+            quarantine_path: quarantine_dir.to_string_lossy().to_string(),
+            settle_ms: 0,
+            dry_run: false,
+        };
+
+        let watcher = FileWatcher::new(&files_config)
+            .unwrap()
+            .with_archive(&archive_config);
+
+        let file = temp_dir.path().join("report.txt");
+        File::create(&file).unwrap();
+
+        let quarantined = watcher.quarantine_file(&file).await.unwrap();
+        assert!(!file.exists());
+        assert_eq!(quarantined, quarantine_dir.join("report.txt"));
+        assert!(quarantined.exists());
+
+        // Archiving is disabled, so nothing is moved
+        let other = temp_dir.path().join("other.txt");
+        File::create(&other).unwrap();
+        assert!(watcher.archive_file(&other).await.unwrap().is_none());
+        assert!(other.exists());
+    }
 }
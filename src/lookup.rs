@@ -1,14 +1,23 @@
 use anyhow::{Context, Result};
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use log::{debug, info, warn};
-use reqwest::{header, Client};
+use rand::Rng;
+use reqwest::{cookie::Jar, header, Client, Url};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::time::Duration;
 
-use crate::config::LookupConfig;
+use crate::config::{LookupAuthConfig, LookupConfig};
+use crate::file_utils::read_input_bytes;
+use crate::upload::{apply_protocol, probe_endpoint};
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Default)]
 pub struct EnrichedRow {
     pub plant: String,
     pub delivery: String,
@@ -20,7 +29,38 @@ pub struct EnrichedRow {
     pub shipment: String,
 }
 
-#[derive(Deserialize)]
+/// Per-file breakdown of the lookup outcome
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LookupReport {
+    pub total_rows: usize,
+    /// Unique, non-empty part numbers; every one of them is queried
+    pub unique_parts: usize,
+    pub parts_found: usize,
+    /// Queried but absent from the lookup response
+    pub parts_not_found: usize,
+    /// Rows skipped before the query because they had no part number
+    pub rows_without_part: usize,
+    /// parts_found / unique_parts; `None` when nothing was queried
+    #[serde(default)]
+    pub hit_rate: Option<f64>,
+}
+
+pub struct Enrichment {
+    pub rows: Vec<EnrichedRow>,
+    pub report: LookupReport,
+    /// One entry per row whose part number the lookup did not resolve
+    pub unmatched: Vec<UnmatchedPart>,
+}
+
+/// A row whose part number was queried but not found, for the `enrich-report` output
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmatchedPart {
+    pub part_no: String,
+    pub plant: String,
+    pub delivery: String,
+}
+
+#[derive(Serialize, Deserialize)]
 struct LookupResponse {
     duns: String,
     cof: String,
@@ -34,8 +74,20 @@ pub struct LookupEnricher {
 
 impl LookupEnricher {
     pub fn new(config: &LookupConfig) -> Result<Self> {
+        // The store also keeps cookies the server sets, e.g. a rotated session
+        let jar = Jar::default();
+        if !config.cookies.is_empty() {
+            let url = Url::parse(&config.url)
+                .with_context(|| format!("Invalid lookup URL: {}", config.url))?;
+            for (name, value) in &config.cookies {
+                jar.add_cookie_str(&format!("{}={}; Path=/", name, value), &url);
+            }
+        }
+
         let client = Client::builder()
             .timeout(Duration::from_secs(config.timeout_secs))
+            .cookie_provider(Arc::new(jar));
+        let client = apply_protocol(client, &config.http_version, config.min_tls_version.as_deref())
             .build()
             .context("Failed to create HTTP client for lookup")?;
 
@@ -45,7 +97,122 @@ impl LookupEnricher {
         })
     }
 
-    pub async fn enrich_tsv_file(&self, tsv_path: &Path) -> Result<Vec<EnrichedRow>> {
+    /// Applies the endpoint-specific auth, falling back to the shared `lookup.cookie`.
+    fn apply_auth(
+        &self,
+        request: reqwest::RequestBuilder,
+        auth: Option<&LookupAuthConfig>,
+    ) -> reqwest::RequestBuilder {
+        let auth = match auth {
+            Some(auth) => auth,
+            None if self.config.cookie.is_empty() => return request,
+            None => return request.header(header::COOKIE, &self.config.cookie),
+        };
+
+        match auth.kind.as_str() {
+            "cookie" => request.header(header::COOKIE, &auth.cookie),
+            "bearer" => request.bearer_auth(&auth.bearer_token),
+            "basic" => request.basic_auth(&auth.basic_username, Some(&auth.basic_password)),
+            _ => request,
+        }
+    }
+
+    /// Sends an empty lookup to confirm the endpoint answers with a 2xx.
+    pub async fn preflight(&self) -> Result<()> {
+        info!("Running lookup preflight against: {}", self.config.url);
+
+        let response = self.send_preflight().await?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN
+        {
+            anyhow::bail!(
+                "Lookup preflight failed: endpoint rejected credentials ({}). Check lookup.read_auth or lookup.cookie.",
+                status
+            );
+        }
+        if !status.is_success() {
+            anyhow::bail!(
+                "Lookup preflight failed with status {}: {}",
+                status,
+                response.text().await.unwrap_or_default()
+            );
+        }
+
+        info!("Lookup preflight succeeded (status: {})", status);
+        Ok(())
+    }
+
+    /// Status of the empty preflight lookup, without judging it.
+    pub async fn probe_lookup(&self) -> Result<reqwest::StatusCode> {
+        Ok(self.send_preflight().await?.status())
+    }
+
+    /// Checks that `post_url` answers with the post auth, without posting any rows.
+    pub async fn probe_post(&self) -> Result<reqwest::StatusCode> {
+        probe_endpoint(|method| {
+            self.apply_auth(
+                self.client.request(method, &self.config.post_url),
+                self.config.post_auth.as_ref(),
+            )
+        })
+        .await
+        .with_context(|| format!("Endpoint unreachable: {}", self.config.post_url))
+    }
+
+    async fn send_preflight(&self) -> Result<reqwest::Response> {
+        let request = self.apply_auth(
+            self.client.get(&self.config.url),
+            self.config.read_auth.as_ref(),
+        );
+
+        request.send().await.with_context(|| {
+            format!(
+                "Lookup preflight failed: endpoint unreachable: {}",
+                self.config.url
+            )
+        })
+    }
+
+    pub async fn enrich_tsv_file(&self, tsv_path: &Path) -> Result<Enrichment> {
+        let enrichment = self.enrich_rows(tsv_path).await?;
+        let report = &enrichment.report;
+        info!(
+            "Lookup report: {} rows, {} unique parts queried, {} found, {} not found, {} rows without a part number",
+            report.total_rows,
+            report.unique_parts,
+            report.parts_found,
+            report.parts_not_found,
+            report.rows_without_part
+        );
+        if let Some(hit_rate) = report.hit_rate {
+            info!(
+                "Lookup hit rate: {:.1}% ({}/{})",
+                hit_rate * 100.0,
+                report.parts_found,
+                report.unique_parts
+            );
+        }
+
+        self.check_report(report)?;
+
+        if let Some(output_file) = &self.config.output_file {
+            self.write_output_file(Path::new(output_file), &enrichment.rows)
+                .await?;
+        }
+
+        Ok(enrichment)
+    }
+
+    /// Parses a file and dedupes its part numbers, without any network calls.
+    pub async fn parse_check(&self, tsv_path: &Path) -> Result<(Vec<EnrichedRow>, Vec<String>)> {
+        let rows = self.parse_tsv_file(tsv_path).await?;
+        let part_numbers = self.dedupe_part_numbers(&rows);
+        Ok((rows, part_numbers))
+    }
+
+    /// Parses and looks up a file, without `fail_on_empty` or `lookup.output_file`.
+    pub async fn enrich_rows(&self, tsv_path: &Path) -> Result<Enrichment> {
         info!(
             "Starting lookup enrichment for file: {}",
             tsv_path.display()
@@ -55,7 +222,11 @@ impl LookupEnricher {
         let base_rows = self.parse_tsv_file(tsv_path).await?;
         if base_rows.is_empty() {
             warn!("No rows found in TSV file");
-            return Ok(base_rows);
+            return Ok(Enrichment {
+                rows: base_rows,
+                report: LookupReport::default(),
+                unmatched: Vec::new(),
+            });
         }
 
         info!("Parsed {} rows from TSV file", base_rows.len());
@@ -63,7 +234,8 @@ impl LookupEnricher {
         // Log sample of parsed rows for debugging
         for (i, row) in base_rows.iter().take(5).enumerate() {
             info!("Sample row {}: Plant='{}', Delivery='{}', Part='{}'", 
-                  i + 1, row.plant, row.delivery, row.part_no);
+                  i + 1, self.redact("plant", &row.plant), self.redact("delivery", &row.delivery),
+                  self.redact("part_no", &row.part_no));
         }
         if base_rows.len() > 5 {
             info!("... and {} more rows", base_rows.len() - 5);
@@ -78,7 +250,10 @@ impl LookupEnricher {
         
         // Log sample part numbers for debugging
         if !part_numbers.is_empty() {
-            info!("Sample part numbers: {}", part_numbers.iter().take(10).cloned().collect::<Vec<_>>().join(", "));
+            let sample: Vec<_> = part_numbers.iter().take(10)
+                .map(|part| self.redact("part_no", part))
+                .collect();
+            info!("Sample part numbers: {}", sample.join(", "));
             if part_numbers.len() > 10 {
                 info!("... and {} more part numbers", part_numbers.len() - 10);
             }
@@ -93,13 +268,21 @@ impl LookupEnricher {
         if part_numbers.is_empty() {
             warn!("No part numbers found for lookup");
             // Return base rows with empty lookup fields - they'll still be posted
-            return Ok(base_rows);
+            let report = self.build_report(&base_rows, &part_numbers, &HashMap::new());
+            return Ok(Enrichment {
+                rows: base_rows,
+                report,
+                unmatched: Vec::new(),
+            });
         }
 
         // Perform chunked lookups
         let lookup_data = self.lookup_chunks(&part_numbers).await?;
         info!("Retrieved lookup data for {} parts", lookup_data.len());
 
+        let report = self.build_report(&base_rows, &part_numbers, &lookup_data);
+        let unmatched = self.unmatched_parts(&base_rows, &lookup_data);
+
         // Merge lookup data into rows (even if lookup_data is empty)
         let enriched_rows = self.merge_lookup_data(base_rows, &lookup_data);
         info!("Enriched {} rows with lookup data", enriched_rows.len());
@@ -114,24 +297,33 @@ impl LookupEnricher {
             for (i, row) in enriched_rows.iter().take(5).enumerate() {
                 let lookup_status = if row.duns.is_empty() { "No lookup data" } else { "With lookup data" };
                 info!("  {}: Plant='{}', Delivery='{}', Part='{}', DUNS='{}', COF='{}', Country='{}' [{}]", 
-                      i + 1, row.plant, row.delivery, row.part_no, row.duns, row.cof, row.country, lookup_status);
+                      i + 1, self.redact("plant", &row.plant), self.redact("delivery", &row.delivery),
+                      self.redact("part_no", &row.part_no), self.redact("duns", &row.duns),
+                      self.redact("cof", &row.cof), self.redact("country", &row.country),
+                      lookup_status);
             }
             if enriched_rows.len() > 5 {
                 info!("  ... and {} more enriched rows", enriched_rows.len() - 5);
             }
         }
 
-        Ok(enriched_rows)
+        Ok(Enrichment {
+            rows: enriched_rows,
+            report,
+            unmatched,
+        })
     }
 
     async fn parse_tsv_file(&self, path: &Path) -> Result<Vec<EnrichedRow>> {
-        let content = tokio::fs::read_to_string(path)
-            .await
-            .with_context(|| format!("Failed to read TSV file: {}", path.display()))?;
+        let bytes = read_input_bytes(path).await?;
+        let content = String::from_utf8(bytes)
+            .with_context(|| format!("TSV file is not valid UTF-8: {}", path.display()))?;
 
         info!("TSV file content length: {} characters", content.len());
-        debug!("First 500 characters of TSV file:\n{}", 
-               content.chars().take(500).collect::<String>());
+        if self.logs_raw_content() {
+            debug!("First 500 characters of TSV file:\n{}", 
+                   content.chars().take(500).collect::<String>());
+        }
 
         let mut rows = Vec::new();
         let mut seen_header = false;
@@ -165,48 +357,43 @@ impl LookupEnricher {
             // Parse data row - handle mixed tab/space separators
             // The format appears to be: Plant\tDelivery\t\tMaterial or Plant\tDelivery\t\t\tMaterial
             // We'll split by tab first, then handle the material column which might have spaces
-            debug!("Line {}: Raw line: '{}'", line_count, trimmed_line);
-            let cols: Vec<&str> = trimmed_line.split('\t').collect();
-            debug!("Line {}: Split into {} columns: {:?}", line_count, cols.len(), cols);
-            
-            if cols.len() < 3 {
-                debug!("Skipping line with insufficient columns ({}): '{}'", cols.len(), trimmed_line);
-                continue;
+            let cols = self.split_fields(trimmed_line);
+            if self.logs_raw_content() {
+                debug!("Line {}: Raw line: '{}'", line_count, trimmed_line);
+                debug!("Line {}: Split into {} columns: {:?}", line_count, cols.len(), cols);
             }
-
-            let plant = cols[0].trim().to_string();
-            let delivery = cols[1].trim().to_string();
             
-            // Find the material column - it should be the last non-empty column
-            let mut part_no = String::new();
-            for i in (2..cols.len()).rev() {
-                let col = cols[i].trim();
-                if !col.is_empty() {
-                    // This might contain spaces, so split by whitespace and take the first part
-                    let material_parts: Vec<&str> = col.split_whitespace().collect();
-                    if !material_parts.is_empty() {
-                        part_no = material_parts[0].to_string();
-                        break;
-                    }
+            let (row, first_word) = if self.config.field_columns.is_empty() {
+                if cols.len() < 3 {
+                    debug!("Skipping line {} with insufficient columns ({})", line_count, cols.len());
+                    continue;
                 }
-            }
+                (Self::positional_row(&cols), true)
+            } else {
+                (self.mapped_row(&cols), false)
+            };
 
-            debug!("Parsed row - Plant: '{}', Delivery: '{}', Part: '{}'", plant, delivery, part_no);
+            debug!("Parsed row - Plant: '{}', Delivery: '{}', Part: '{}'",
+                   self.redact("plant", &row.plant), self.redact("delivery", &row.delivery),
+                   self.redact("part_no", &row.part_no));
 
             // Skip empty rows
-            if plant.is_empty() && delivery.is_empty() && part_no.is_empty() {
+            if row.plant.is_empty() && row.delivery.is_empty() && row.part_no.is_empty() {
                 continue;
             }
 
-            rows.push(EnrichedRow {
-                plant,
-                delivery,
-                part_no,
-                duns: String::new(),
-                cof: String::new(),
-                country: String::new(),
-                shipment: String::new(),
-            });
+            for part in self.cell_parts(&row.part_no) {
+                // A positional material cell may carry a description after the part number
+                let part_no = if first_word {
+                    part.split_whitespace().next().unwrap_or_default()
+                } else {
+                    part
+                };
+                rows.push(EnrichedRow {
+                    part_no: part_no.to_string(),
+                    ..row.clone()
+                });
+            }
         }
 
         info!("TSV parsing complete: {} total lines processed, header found: {}, {} data rows parsed", 
@@ -215,6 +402,86 @@ impl LookupEnricher {
         Ok(rows)
     }
 
+    /// Plant and delivery are the first two columns; the part number is taken from
+    /// the last non-empty column, the material cell.
+    fn positional_row(cols: &[String]) -> EnrichedRow {
+        let plant = cols[0].trim().to_string();
+        let delivery = cols[1].trim().to_string();
+
+        // Find the material column - it should be the last non-empty column
+        let part_no = cols[2..]
+            .iter()
+            .rev()
+            .map(|col| col.trim())
+            .find(|col| !col.is_empty())
+            .unwrap_or_default()
+            .to_string();
+
+        EnrichedRow {
+            plant,
+            delivery,
+            part_no,
+            ..Default::default()
+        }
+    }
+
+    /// The parts listed in a material cell, split on `lookup.split_parts_delimiter`.
+    /// Without a delimiter, or when the cell is empty, the cell is one part.
+    fn cell_parts<'a>(&self, cell: &'a str) -> Vec<&'a str> {
+        let parts: Vec<&str> = match self.config.split_parts_delimiter.as_deref() {
+            Some(delimiter) => cell
+                .split(delimiter)
+                .map(str::trim)
+                .filter(|part| !part.is_empty())
+                .collect(),
+            None => Vec::new(),
+        };
+        if parts.is_empty() {
+            vec![cell]
+        } else {
+            parts
+        }
+    }
+
+    /// Fills each field from its `lookup.field_columns` index; a column past the
+    /// end of the line leaves the field empty.
+    fn mapped_row(&self, cols: &[String]) -> EnrichedRow {
+        let mut row = EnrichedRow::default();
+        for (field, &index) in &self.config.field_columns {
+            let value = cols.get(index).map(|col| col.trim().to_string()).unwrap_or_default();
+            match field.as_str() {
+                "plant" => row.plant = value,
+                "delivery" => row.delivery = value,
+                "part_no" => row.part_no = value,
+                "duns" => row.duns = value,
+                "cof" => row.cof = value,
+                "country" => row.country = value,
+                "shipment" => row.shipment = value,
+                _ => {}
+            }
+        }
+        row
+    }
+
+    /// Tokenizes one TSV line, honoring quoted fields that contain tabs.
+    fn split_fields(&self, line: &str) -> Vec<String> {
+        let mut builder = csv::ReaderBuilder::new();
+        builder.delimiter(b'\t').has_headers(false).flexible(true);
+        match self.config.tsv_quote.as_bytes().first() {
+            Some(&quote) => builder.quote(quote),
+            None => builder.quoting(false),
+        };
+
+        let mut record = csv::StringRecord::new();
+        match builder.from_reader(line.as_bytes()).read_record(&mut record) {
+            Ok(_) => record.iter().map(str::to_string).collect(),
+            Err(e) => {
+                warn!("Failed to tokenize TSV line, falling back to plain split: {}", e);
+                line.split('\t').map(str::to_string).collect()
+            }
+        }
+    }
+
     fn dedupe_part_numbers(&self, rows: &[EnrichedRow]) -> Vec<String> {
         let mut seen = HashSet::new();
         let mut parts = Vec::new();
@@ -224,13 +491,14 @@ impl LookupEnricher {
         for row in rows {
             if row.part_no.trim().is_empty() {
                 empty_count += 1;
-                debug!("Skipping row with empty part number: Plant='{}', Delivery='{}'", row.plant, row.delivery);
-            } else if seen.insert(row.part_no.clone()) {
-                parts.push(row.part_no.clone());
-                debug!("Added unique part number: '{}'", row.part_no);
+                debug!("Skipping row with empty part number: Plant='{}', Delivery='{}'",
+                       self.redact("plant", &row.plant), self.redact("delivery", &row.delivery));
+            } else if seen.insert(self.normalize_key(&row.part_no)) {
+                parts.push(self.normalize_key(&row.part_no));
+                debug!("Added unique part number: '{}'", self.redact("part_no", &row.part_no));
             } else {
                 duplicate_count += 1;
-                debug!("Skipping duplicate part number: '{}'", row.part_no);
+                debug!("Skipping duplicate part number: '{}'", self.redact("part_no", &row.part_no));
             }
         }
 
@@ -244,16 +512,58 @@ impl LookupEnricher {
         &self,
         part_numbers: &[String],
     ) -> Result<HashMap<String, LookupResponse>> {
-        let mut all_lookup_data = HashMap::new();
+        let progress_path = self.config.progress_path.as_deref().map(Path::new);
+        let mut all_lookup_data = match progress_path {
+            Some(path) => load_progress(path),
+            None => HashMap::new(),
+        };
+        let remaining: Vec<String> = part_numbers
+            .iter()
+            .filter(|part| !all_lookup_data.contains_key(&self.normalize_key(part)))
+            .cloned()
+            .collect();
+        if remaining.len() < part_numbers.len() {
+            info!("Resuming lookup: {} of {} parts already resolved in the progress file",
+                  part_numbers.len() - remaining.len(), part_numbers.len());
+        }
 
-        for chunk in part_numbers.chunks(self.config.chunk_size) {
+        for (i, chunk) in remaining.chunks(self.config.chunk_size).enumerate() {
+            if i > 0 {
+                let pause = self.chunk_pause();
+                if !pause.is_zero() {
+                    debug!("Waiting {}ms before the next lookup chunk", pause.as_millis());
+                    tokio::time::sleep(pause).await;
+                }
+            }
             let chunk_data = self.lookup_single_chunk(chunk).await?;
-            all_lookup_data.extend(chunk_data);
+            all_lookup_data.extend(
+                chunk_data
+                    .into_iter()
+                    .map(|(part, lookup)| (self.normalize_key(&part), lookup)),
+            );
+            if let Some(path) = progress_path {
+                save_progress(path, &all_lookup_data)?;
+            }
+        }
+
+        if let Some(path) = progress_path.filter(|path| path.exists()) {
+            std::fs::remove_file(path).with_context(|| {
+                format!("Failed to remove lookup progress file: {}", path.display())
+            })?;
         }
 
         Ok(all_lookup_data)
     }
 
+    /// `chunk_delay_ms` plus a random 0..=`chunk_jitter_ms`.
+    fn chunk_pause(&self) -> Duration {
+        let jitter = match self.config.chunk_jitter_ms {
+            0 => 0,
+            max => rand::thread_rng().gen_range(0..=max),
+        };
+        Duration::from_millis(self.config.chunk_delay_ms + jitter)
+    }
+
     async fn lookup_single_chunk(
         &self,
         part_numbers: &[String],
@@ -263,19 +573,20 @@ impl LookupEnricher {
         let url = format!("{}{}", self.config.url, encoded_parts);
 
         info!("Looking up chunk: {} parts", part_numbers.len());
-        debug!("Lookup URL: {}", url);
-
-        let mut request = self.client.get(&url);
-
-        // Add cookie if configured
-        if !self.config.cookie.is_empty() {
-            request = request.header(header::COOKIE, &self.config.cookie);
+        if self.logs_raw_content() {
+            debug!("Lookup URL: {}", url);
         }
 
+        let request = self.apply_auth(self.client.get(&url), self.config.read_auth.as_ref());
+
+        // The full URL carries every part number in the chunk, and so does reqwest's error
+        let raw = self.logs_raw_content();
+        let target = if raw { &url } else { &self.config.url };
         let response = request
             .send()
             .await
-            .with_context(|| format!("Failed to send lookup request to: {}", url))?;
+            .map_err(|e| if raw { e } else { e.without_url() })
+            .with_context(|| format!("Failed to send lookup request to: {}", target))?;
 
         if !response.status().is_success() {
             anyhow::bail!(
@@ -285,13 +596,19 @@ impl LookupEnricher {
             );
         }
 
+        let content_type = response_content_type(&response);
         // Get response text first for debugging
         let response_text = response.text().await
             .with_context(|| "Failed to read response body")?;
+        if self.config.detect_login_html && is_html(content_type.as_deref(), &response_text) {
+            anyhow::bail!("Lookup returned an HTML page instead of JSON: session expired, refresh cookie");
+        }
         
         info!("Lookup response length: {} characters", response_text.len());
-        debug!("Lookup response content (first 1000 chars): {}", 
-               response_text.chars().take(1000).collect::<String>());
+        if self.logs_raw_content() {
+            debug!("Lookup response content (first 1000 chars): {}", 
+                   response_text.chars().take(1000).collect::<String>());
+        }
         
         // Try to parse as JSON - handle both array and object responses
         let lookup_map: HashMap<String, LookupResponse> = match serde_json::from_str::<HashMap<String, LookupResponse>>(&response_text) {
@@ -301,27 +618,28 @@ impl LookupEnricher {
                 info!("Response is not a JSON object, trying to parse as array...");
                 let array_response: Vec<serde_json::Value> = serde_json::from_str(&response_text)
                     .with_context(|| {
+                        if !self.logs_raw_content() {
+                            return "Failed to parse lookup response as JSON array or object".to_string();
+                        }
                         format!("Failed to parse lookup response as JSON array or object. First 500 chars: {}", 
                                 response_text.chars().take(500).collect::<String>())
                     })?;
                 
                 info!("Successfully parsed as JSON array with {} items", array_response.len());
                 
-                // Convert array to HashMap - assuming each item has a "part" or "part_no" field as key
+                // Convert array to HashMap, keyed by the first configured key field present
                 let mut map = HashMap::new();
                 for item in &array_response {
-                    if let (Some(part_key), Some(duns)) = (
-                        item.get("part").or_else(|| item.get("part_no")).or_else(|| item.get("material")),
+                    if let (Some(part_no), Some(duns)) = (
+                        self.array_item_key(item),
                         item.get("duns").and_then(|d| d.as_str())
                     ) {
-                        if let Some(part_no) = part_key.as_str() {
-                            let lookup_response = LookupResponse {
-                                duns: duns.to_string(),
-                                cof: item.get("cof").and_then(|c| c.as_str()).unwrap_or("").to_string(),
-                                country: item.get("country").and_then(|c| c.as_str()).unwrap_or("").to_string(),
-                            };
-                            map.insert(part_no.to_string(), lookup_response);
-                        }
+                        let lookup_response = LookupResponse {
+                            duns: duns.to_string(),
+                            cof: item.get("cof").and_then(|c| c.as_str()).unwrap_or("").to_string(),
+                            country: item.get("country").and_then(|c| c.as_str()).unwrap_or("").to_string(),
+                        };
+                        map.insert(part_no.to_string(), lookup_response);
                     }
                 }
                 
@@ -329,8 +647,13 @@ impl LookupEnricher {
                     if array_response.is_empty() {
                         info!("Lookup API returned empty array - no lookup data found for any parts. Proceeding with original data only.");
                     } else {
+                        let structure = if self.logs_raw_content() {
+                            serde_json::to_string_pretty(&array_response).unwrap_or_default()
+                        } else {
+                            "(hidden by lookup.log_redact_fields)".to_string()
+                        };
                         warn!("Could not extract part numbers from array response. Array structure: {}", 
-                              serde_json::to_string_pretty(&array_response).unwrap_or_default());
+                              structure);
                         info!("Proceeding with original data only (no lookup enrichment).");
                     }
                 }
@@ -346,7 +669,8 @@ impl LookupEnricher {
             info!("Sample enriched data from GET request:");
             for (i, (part_no, lookup_data)) in lookup_map.iter().take(5).enumerate() {
                 info!("  {}: Part='{}', DUNS='{}', COF='{}', Country='{}'", 
-                      i + 1, part_no, lookup_data.duns, lookup_data.cof, lookup_data.country);
+                      i + 1, self.redact("part_no", part_no), self.redact("duns", &lookup_data.duns),
+                      self.redact("cof", &lookup_data.cof), self.redact("country", &lookup_data.country));
             }
             if lookup_map.len() > 5 {
                 info!("  ... and {} more enriched records", lookup_map.len() - 5);
@@ -358,56 +682,355 @@ impl LookupEnricher {
         Ok(lookup_map)
     }
 
+    /// A row value for log output, masked to its last 4 characters when `field` is
+    /// in `lookup.log_redact_fields`. Posted and written rows are never masked.
+    fn redact<'a>(&self, field: &str, value: &'a str) -> Cow<'a, str> {
+        if !self.config.log_redact_fields.iter().any(|f| f == field) {
+            return Cow::Borrowed(value);
+        }
+        // Short values would be shown whole, so they are masked completely
+        let count = value.chars().count();
+        if count <= 4 {
+            return Cow::Borrowed("****");
+        }
+        let visible: String = value.chars().skip(count - 4).collect();
+        Cow::Owned(format!("****{}", visible))
+    }
+
+    /// Raw lines, URLs, and response bodies carry every field, so they are only
+    /// logged when nothing is redacted.
+    fn logs_raw_content(&self) -> bool {
+        self.config.log_redact_fields.is_empty()
+    }
+
     fn merge_lookup_data(
         &self,
         mut rows: Vec<EnrichedRow>,
         lookup_data: &HashMap<String, LookupResponse>,
     ) -> Vec<EnrichedRow> {
         for row in &mut rows {
-            if let Some(lookup) = lookup_data.get(&row.part_no) {
+            if let Some(lookup) = lookup_data.get(&self.normalize_key(&row.part_no)) {
                 row.duns = lookup.duns.clone();
                 row.cof = lookup.cof.clone();
                 row.country = lookup.country.clone();
+            } else if self.config.mark_unmatched && !row.part_no.trim().is_empty() {
+                // Let the consumer tell a lookup miss from a genuinely empty value
+                row.duns = self.config.unmatched_sentinel.clone();
+                row.cof = self.config.unmatched_sentinel.clone();
+                row.country = self.config.unmatched_sentinel.clone();
             }
         }
 
         rows
     }
 
-    pub async fn post_enriched_data(&self, rows: &[EnrichedRow]) -> Result<()> {
-        let json_data =
-            serde_json::to_string(rows).context("Failed to serialize enriched rows to JSON")?;
+    /// Breaks down what happened to each part: queried, found, or never queried.
+    fn build_report(
+        &self,
+        rows: &[EnrichedRow],
+        part_numbers: &[String],
+        lookup_data: &HashMap<String, LookupResponse>,
+    ) -> LookupReport {
+        let parts_found = part_numbers
+            .iter()
+            .filter(|part| lookup_data.contains_key(*part))
+            .count();
+
+        LookupReport {
+            total_rows: rows.len(),
+            unique_parts: part_numbers.len(),
+            parts_found,
+            parts_not_found: part_numbers.len() - parts_found,
+            rows_without_part: rows.iter().filter(|row| row.part_no.trim().is_empty()).count(),
+            hit_rate: (!part_numbers.is_empty())
+                .then(|| parts_found as f64 / part_numbers.len() as f64),
+        }
+    }
 
-        let form_data = vec![("tableData", json_data.as_str()), ("save", "")];
+    /// Rows with a part number the lookup response does not contain, in file order.
+    fn unmatched_parts(
+        &self,
+        rows: &[EnrichedRow],
+        lookup_data: &HashMap<String, LookupResponse>,
+    ) -> Vec<UnmatchedPart> {
+        rows.iter()
+            .filter(|row| !row.part_no.trim().is_empty())
+            .filter(|row| !lookup_data.contains_key(&self.normalize_key(&row.part_no)))
+            .map(|row| UnmatchedPart {
+                part_no: row.part_no.clone(),
+                plant: row.plant.clone(),
+                delivery: row.delivery.clone(),
+            })
+            .collect()
+    }
 
-        debug!(
-            "Posting {} enriched rows to: {}",
-            rows.len(),
-            self.config.post_url
-        );
+    /// Writes JSON when `path` ends in `.json`, CSV with a header row otherwise.
+    pub async fn write_unmatched(&self, unmatched: &[UnmatchedPart], path: &Path) -> Result<()> {
+        let content = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            serde_json::to_vec_pretty(unmatched).context("Failed to serialize unmatched parts")?
+        } else {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for part in unmatched {
+                writer
+                    .serialize(part)
+                    .context("Failed to serialize unmatched parts")?;
+            }
+            writer
+                .into_inner()
+                .context("Failed to serialize unmatched parts")?
+        };
 
-        let mut request = self.client.post(&self.config.post_url).form(&form_data);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await.with_context(|| {
+                format!("Failed to create report directory: {}", parent.display())
+            })?;
+        }
+        tokio::fs::write(path, content)
+            .await
+            .with_context(|| format!("Failed to write unmatched parts: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// With `fail_on_empty`, an all-miss lookup aborts so the next cycle retries
+    /// instead of posting un-enriched rows.
+    fn check_report(&self, report: &LookupReport) -> Result<()> {
+        if self.config.fail_on_empty && report.unique_parts > 0 && report.parts_found == 0 {
+            anyhow::bail!(
+                "Lookup matched none of the {} queried parts; the lookup data may not be ready yet (lookup.fail_on_empty)",
+                report.unique_parts
+            );
+        }
+        Ok(())
+    }
 
-        // Add cookie if configured
-        if !self.config.cookie.is_empty() {
-            request = request.header(header::COOKIE, &self.config.cookie);
+    /// Applies `key_normalize` steps in order so query and response keys compare equal.
+    fn normalize_key(&self, key: &str) -> String {
+        let mut key = key.to_string();
+        for step in &self.config.key_normalize {
+            key = match step.as_str() {
+                "trim" => key.trim().to_string(),
+                "uppercase" => key.to_uppercase(),
+                "strip_leading_zeros" => {
+                    let stripped = key.trim_start_matches('0');
+                    if stripped.is_empty() && !key.is_empty() {
+                        "0".to_string()
+                    } else {
+                        stripped.to_string()
+                    }
+                }
+                _ => key,
+            };
         }
+        key
+    }
+
+    /// Returns the part number of an array response item, trying `array_key_fields` in order.
+    fn array_item_key<'a>(&self, item: &'a serde_json::Value) -> Option<&'a str> {
+        self.config
+            .array_key_fields
+            .iter()
+            .find_map(|field| item.get(field).and_then(|v| v.as_str()))
+    }
+
+    /// Writes all rows as one JSON array to a timestamped copy of `path`. This is the
+    /// `tableData` of a single form post; jsonl posts and `post_chunk_size` batches differ.
+    async fn write_output_file(&self, path: &Path, rows: &[EnrichedRow]) -> Result<PathBuf> {
+        let json_data =
+            serde_json::to_string(rows).context("Failed to serialize enriched rows to JSON")?;
 
-        let response = request.send().await.with_context(|| {
-            format!("Failed to send enriched data to: {}", self.config.post_url)
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("enriched");
+        let extension = path
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|s| format!(".{}", s))
+            .unwrap_or_default();
+        let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
+        let target = path.with_file_name(format!("{}_{}{}", stem, timestamp, extension));
+
+        if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await.with_context(|| {
+                format!("Failed to create enrichment output directory: {}", parent.display())
+            })?;
+        }
+        tokio::fs::write(&target, json_data).await.with_context(|| {
+            format!("Failed to write enrichment output: {}", target.display())
         })?;
 
-        if !response.status().is_success() {
-            anyhow::bail!(
-                "Post request failed with status {}: {}",
-                response.status(),
-                response.text().await.unwrap_or_default()
+        info!("Wrote {} enriched rows to {}", rows.len(), target.display());
+        Ok(target)
+    }
+
+    pub async fn post_enriched_data(&self, rows: &[EnrichedRow]) -> Result<()> {
+        let batches: Vec<&[EnrichedRow]> = match self.config.post_chunk_size {
+            // An empty result is still posted once, as before chunking existed
+            0 => vec![rows],
+            _ if rows.is_empty() => vec![rows],
+            size => rows.chunks(size).collect(),
+        };
+
+        let mut posted = 0;
+        for (i, batch) in batches.iter().enumerate() {
+            debug!(
+                "Posting batch {} of {} ({} enriched rows) to: {}",
+                i + 1,
+                batches.len(),
+                batch.len(),
+                self.config.post_url
             );
+            self.post_batch(batch).await.with_context(|| {
+                format!(
+                    "Failed to post batch {} of {} ({} of {} rows already posted)",
+                    i + 1,
+                    batches.len(),
+                    posted,
+                    rows.len()
+                )
+            })?;
+            posted += batch.len();
         }
 
         info!("Successfully posted {} enriched rows", rows.len());
         Ok(())
     }
+
+    /// The batch as a `tableData` form, or as one JSON object per line for "jsonl".
+    fn post_body(&self, rows: &[EnrichedRow]) -> Result<(&'static str, String)> {
+        if self.config.post_format == "jsonl" {
+            let mut body = String::new();
+            for row in rows {
+                let line =
+                    serde_json::to_string(row).context("Failed to serialize enriched row to JSON")?;
+                body.push_str(&line);
+                body.push('\n');
+            }
+            return Ok(("application/x-ndjson", body));
+        }
+
+        let json_data =
+            serde_json::to_string(rows).context("Failed to serialize enriched rows to JSON")?;
+        let form_body = format!("tableData={}&save=", urlencoding::encode(&json_data));
+        Ok(("application/x-www-form-urlencoded", form_body))
+    }
+
+    /// Posts one batch in `post_format`, retrying up to `post_max_attempts`. The body
+    /// is encoded (and gzipped) once and copied into a fresh request per attempt.
+    async fn post_batch(&self, rows: &[EnrichedRow]) -> Result<()> {
+        let (content_type, post_body) = self.post_body(rows)?;
+        let body = if self.config.post_compress {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(post_body.as_bytes())
+                .context("Failed to compress enriched rows")?;
+            encoder.finish().context("Failed to compress enriched rows")?
+        } else {
+            post_body.into_bytes()
+        };
+
+        let max_attempts = self.config.post_max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let mut request = self
+                .client
+                .post(&self.config.post_url)
+                .header(header::CONTENT_TYPE, content_type)
+                .body(body.clone());
+            if self.config.post_compress {
+                request = request.header(header::CONTENT_ENCODING, "gzip");
+            }
+            let request = self.apply_auth(request, self.config.post_auth.as_ref());
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    if self.config.detect_login_html {
+                        let content_type = response_content_type(&response);
+                        let text = response.text().await.unwrap_or_default();
+                        if is_html(content_type.as_deref(), &text) {
+                            anyhow::bail!("Post returned an HTML page instead of a result: session expired, refresh cookie");
+                        }
+                    }
+                    return Ok(());
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let error = anyhow::anyhow!(
+                        "Post request failed with status {}: {}",
+                        status,
+                        response.text().await.unwrap_or_default()
+                    );
+                    if !(status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS)
+                    {
+                        return Err(error);
+                    }
+                    error
+                }
+                Err(e) => anyhow::Error::new(e).context(format!(
+                    "Failed to send enriched data to: {}",
+                    self.config.post_url
+                )),
+            };
+
+            if attempt >= max_attempts {
+                return Err(error);
+            }
+            warn!(
+                "Post attempt {} of {} failed, retrying: {:#}",
+                attempt, max_attempts, error
+            );
+            tokio::time::sleep(Duration::from_millis(500 * attempt as u64)).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn response_content_type(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Whether a response is an HTML page, e.g. the login page an expired session is
+/// redirected to, by its Content-Type or its opening tag.
+fn is_html(content_type: Option<&str>, body: &str) -> bool {
+    if content_type.is_some_and(|ct| ct.to_ascii_lowercase().contains("text/html")) {
+        return true;
+    }
+    let start: String = body.trim_start().chars().take(15).collect();
+    let start = start.to_ascii_lowercase();
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// A missing or unreadable progress file means starting from scratch.
+fn load_progress(path: &Path) -> HashMap<String, LookupResponse> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return HashMap::new(),
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable lookup progress file {}: {}", path.display(), e);
+        HashMap::new()
+    })
+}
+
+/// Writes through a temporary file so a crash never leaves a truncated progress file.
+fn save_progress(path: &Path, lookup_data: &HashMap<String, LookupResponse>) -> Result<()> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent).with_context(|| {
+        format!("Failed to create lookup progress directory: {}", parent.display())
+    })?;
+
+    let json = serde_json::to_string(lookup_data).context("Failed to serialize lookup progress")?;
+    let mut temp = tempfile::NamedTempFile::new_in(parent)
+        .with_context(|| format!("Failed to create lookup progress file in {}", parent.display()))?;
+    temp.write_all(json.as_bytes())
+        .with_context(|| format!("Failed to write lookup progress file: {}", path.display()))?;
+    temp.persist(path)
+        .with_context(|| format!("Failed to replace lookup progress file: {}", path.display()))?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -423,6 +1046,31 @@ mod tests {
             cookie: String::new(),
             timeout_secs: 30,
             post_url: "http://localhost:8080/post".to_string(),
+            preflight: false,
+            mark_unmatched: false,
+            unmatched_sentinel: "NOT_FOUND".to_string(),
+            tsv_quote: "\"".to_string(),
+            output_file: None,
+            post: true,
+            array_key_fields: vec!["part".to_string(), "part_no".to_string(), "material".to_string()],
+            key_normalize: Vec::new(),
+            read_auth: None,
+            post_auth: None,
+            fail_on_empty: false,
+            field_columns: HashMap::new(),
+            cookies: HashMap::new(),
+            post_chunk_size: 0,
+            chunk_delay_ms: 0,
+            chunk_jitter_ms: 0,
+            progress_path: None,
+            split_parts_delimiter: None,
+            detect_login_html: false,
+            post_compress: false,
+            post_max_attempts: 1,
+            post_format: "form".to_string(),
+            log_redact_fields: Vec::new(),
+            http_version: "auto".to_string(),
+            min_tls_version: None,
         }
     }
 
@@ -541,6 +1189,138 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn test_parse_tsv_with_split_parts_delimiter() {
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let test_file = temp_dir.path().join("test.tsv");
+        let tsv_content = "Plant\tDelivery\tMaterial\nTEST01\t1234567890\t987654321 Bolt, 456789123\nTEST02\t1234567891\t789123456\n";
+        tokio::fs::write(&test_file, tsv_content).await.unwrap();
+
+        let mut config = create_test_config();
+        config.split_parts_delimiter = Some(",".to_string());
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let rows = enricher.parse_tsv_file(&test_file).await.unwrap();
+        let parsed: Vec<(&str, &str, &str)> = rows
+            .iter()
+            .map(|row| (row.plant.as_str(), row.delivery.as_str(), row.part_no.as_str()))
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                ("TEST01", "1234567890", "987654321"),
+                ("TEST01", "1234567890", "456789123"),
+                ("TEST02", "1234567891", "789123456"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_tsv_with_field_columns() {
+        use tokio::fs::write;
+        use tempfile::tempdir;
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let temp_dir = tempdir().unwrap();
+            let test_file = temp_dir.path().join("test.tsv");
+
+            let tsv_content = "Plant\tShipment\tDelivery\tMaterial\tQty\nTEST01\tSH9\t1234567890\t987654321\t5\nTEST02\tSH8\t1234567891\n";
+            write(&test_file, tsv_content).await.unwrap();
+
+            let mut config = create_test_config();
+            config.field_columns = HashMap::from([
+                ("plant".to_string(), 0),
+                ("shipment".to_string(), 1),
+                ("delivery".to_string(), 2),
+                ("part_no".to_string(), 3),
+            ]);
+            let enricher = LookupEnricher::new(&config).unwrap();
+
+            let rows = enricher.parse_tsv_file(&test_file).await.unwrap();
+
+            assert_eq!(rows.len(), 2);
+            assert_eq!(rows[0].plant, "TEST01");
+            assert_eq!(rows[0].shipment, "SH9");
+            assert_eq!(rows[0].delivery, "1234567890");
+            assert_eq!(rows[0].part_no, "987654321");
+            assert_eq!(rows[1].delivery, "1234567891");
+            assert_eq!(rows[1].part_no, "");
+        });
+    }
+
+    #[test]
+    fn test_parse_tsv_with_quoted_tabs() {
+        use tokio::fs::write;
+        use tempfile::tempdir;
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let temp_dir = tempdir().unwrap();
+            let test_file = temp_dir.path().join("test.tsv");
+
+            let tsv_content = "Plant\tDelivery\tMaterial\n\"TEST\t01\"\t1234567890\t987654321\n";
+            write(&test_file, tsv_content).await.unwrap();
+
+            let config = create_test_config();
+            let enricher = LookupEnricher::new(&config).unwrap();
+
+            let rows = enricher.parse_tsv_file(&test_file).await.unwrap();
+
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0].plant, "TEST\t01");
+            assert_eq!(rows[0].delivery, "1234567890");
+            assert_eq!(rows[0].part_no, "987654321");
+        });
+    }
+
+    #[test]
+    fn test_array_item_key_uses_configured_fields() {
+        let mut config = create_test_config();
+        let item = serde_json::json!({ "materialNumber": "ABC123", "duns": "111" });
+
+        let enricher = LookupEnricher::new(&config).unwrap();
+        assert_eq!(enricher.array_item_key(&item), None);
+
+        config.array_key_fields = vec!["part".to_string(), "materialNumber".to_string()];
+        let enricher = LookupEnricher::new(&config).unwrap();
+        assert_eq!(enricher.array_item_key(&item), Some("ABC123"));
+    }
+
+    #[test]
+    fn test_write_output_file() {
+        use tempfile::tempdir;
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let temp_dir = tempdir().unwrap();
+            let config = create_test_config();
+            let enricher = LookupEnricher::new(&config).unwrap();
+
+            let rows = vec![EnrichedRow {
+                plant: "PLT01".to_string(),
+                delivery: "123".to_string(),
+                part_no: "ABC".to_string(),
+                duns: String::new(),
+                cof: String::new(),
+                country: String::new(),
+                shipment: String::new(),
+            }];
+
+            let written = enricher
+                .write_output_file(&temp_dir.path().join("audit").join("enriched.json"), &rows)
+                .await
+                .unwrap();
+
+            let name = written.file_name().unwrap().to_string_lossy().to_string();
+            assert!(name.starts_with("enriched_") && name.ends_with(".json"));
+            let content = std::fs::read_to_string(&written).unwrap();
+            assert_eq!(content, serde_json::to_string(&rows).unwrap());
+        });
+    }
+
     #[test]
     fn test_merge_lookup_data() {
         let config = create_test_config();
@@ -584,4 +1364,438 @@ mod tests {
         assert_eq!(enriched[0].country, "Test Country");
         assert_eq!(enriched[1].duns, ""); // No lookup data for TEST002
     }
+
+    #[test]
+    fn test_merge_lookup_data_marks_unmatched() {
+        let mut config = create_test_config();
+        config.mark_unmatched = true;
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let rows = vec![EnrichedRow {
+            plant: "TEST01".to_string(),
+            delivery: "DEL001".to_string(),
+            part_no: "TEST001".to_string(),
+            duns: String::new(),
+            cof: String::new(),
+            country: String::new(),
+            shipment: String::new(),
+        }];
+
+        let enriched = enricher.merge_lookup_data(rows, &HashMap::new());
+
+        assert_eq!(enriched[0].duns, "NOT_FOUND");
+        assert_eq!(enriched[0].cof, "NOT_FOUND");
+        assert_eq!(enriched[0].country, "NOT_FOUND");
+    }
+
+    #[test]
+    fn test_merge_lookup_data_with_key_normalize() {
+        let mut config = create_test_config();
+        config.key_normalize = vec!["trim".to_string(), "strip_leading_zeros".to_string(), "uppercase".to_string()];
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let rows = vec![EnrichedRow {
+            plant: "TEST01".to_string(),
+            delivery: "DEL001".to_string(),
+            part_no: " 000abc123".to_string(),
+            duns: String::new(),
+            cof: String::new(),
+            country: String::new(),
+            shipment: String::new(),
+        }];
+        assert_eq!(enricher.dedupe_part_numbers(&rows), vec!["ABC123"]);
+
+        let mut lookup_data = HashMap::new();
+        lookup_data.insert(
+            enricher.normalize_key("abc123"),
+            LookupResponse {
+                duns: "123456789".to_string(),
+                cof: "US".to_string(),
+                country: "United States".to_string(),
+            },
+        );
+
+        let enriched = enricher.merge_lookup_data(rows, &lookup_data);
+        assert_eq!(enriched[0].duns, "123456789");
+        assert_eq!(enriched[0].part_no, " 000abc123");
+    }
+
+    #[test]
+    fn test_build_report() {
+        let config = create_test_config();
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let row = |part_no: &str| EnrichedRow {
+            plant: "TEST01".to_string(),
+            delivery: "DEL001".to_string(),
+            part_no: part_no.to_string(),
+            duns: String::new(),
+            cof: String::new(),
+            country: String::new(),
+            shipment: String::new(),
+        };
+        let rows = vec![row("A"), row("A"), row("B"), row("")];
+        let part_numbers = enricher.dedupe_part_numbers(&rows);
+
+        let mut lookup_data = HashMap::new();
+        lookup_data.insert(
+            "A".to_string(),
+            LookupResponse {
+                duns: "1".to_string(),
+                cof: String::new(),
+                country: String::new(),
+            },
+        );
+
+        let report = enricher.build_report(&rows, &part_numbers, &lookup_data);
+        assert_eq!(report.total_rows, 4);
+        assert_eq!(report.unique_parts, 2);
+        assert_eq!(report.parts_found, 1);
+        assert_eq!(report.parts_not_found, 1);
+        assert_eq!(report.rows_without_part, 1);
+        assert_eq!(report.hit_rate, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn test_unmatched_parts_report() {
+        let config = create_test_config();
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let row = |plant: &str, part_no: &str| EnrichedRow {
+            plant: plant.to_string(),
+            delivery: "DEL001".to_string(),
+            part_no: part_no.to_string(),
+            ..Default::default()
+        };
+        let rows = vec![
+            row("TEST01", "A"),
+            row("TEST01", "B"),
+            row("TEST02", "B"),
+            row("TEST01", ""),
+        ];
+        let mut lookup_data = HashMap::new();
+        lookup_data.insert(
+            "A".to_string(),
+            LookupResponse {
+                duns: "1".to_string(),
+                cof: String::new(),
+                country: String::new(),
+            },
+        );
+
+        let unmatched = enricher.unmatched_parts(&rows, &lookup_data);
+        let plants: Vec<_> = unmatched.iter().map(|part| part.plant.as_str()).collect();
+        assert_eq!(plants, vec!["TEST01", "TEST02"]);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let csv_path = temp_dir.path().join("unmatched.csv");
+        enricher.write_unmatched(&unmatched, &csv_path).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&csv_path).unwrap(),
+            "part_no,plant,delivery\nB,TEST01,DEL001\nB,TEST02,DEL001\n"
+        );
+
+        let json_path = temp_dir.path().join("unmatched.json");
+        enricher.write_unmatched(&unmatched, &json_path).await.unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(json[1]["plant"], "TEST02");
+    }
+
+    #[test]
+    fn test_fail_on_empty() {
+        let mut config = create_test_config();
+        let no_matches = LookupReport {
+            total_rows: 3,
+            unique_parts: 2,
+            ..Default::default()
+        };
+
+        let enricher = LookupEnricher::new(&config).unwrap();
+        assert!(enricher.check_report(&no_matches).is_ok());
+
+        config.fail_on_empty = true;
+        let enricher = LookupEnricher::new(&config).unwrap();
+        assert!(enricher.check_report(&no_matches).is_err());
+        // Nothing queried is not an empty result
+        assert!(enricher.check_report(&LookupReport::default()).is_ok());
+        let one_match = LookupReport {
+            parts_found: 1,
+            ..no_matches
+        };
+        assert!(enricher.check_report(&one_match).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_seeded_cookies_are_sent() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let n = socket.read(&mut buf).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_lowercase()
+        });
+
+        let mut config = create_test_config();
+        config.url = format!("http://{}/api/lookup?part=", addr);
+        config.cookies = HashMap::from([
+            ("session".to_string(), "abc".to_string()),
+            ("csrf".to_string(), "xyz".to_string()),
+        ]);
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let status = enricher.probe_lookup().await.unwrap();
+        assert!(status.is_success());
+
+        let request = server.await.unwrap();
+        let cookie_line = request.lines().find(|l| l.starts_with("cookie:")).unwrap();
+        assert!(cookie_line.contains("session=abc"));
+        assert!(cookie_line.contains("csrf=xyz"));
+    }
+
+    #[tokio::test]
+    async fn test_post_chunked_compressed_with_retry() {
+        use std::io::Read;
+        use std::sync::Mutex;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        tokio::spawn(async move {
+            let responses = ["500 Internal Server Error", "200 OK", "200 OK"];
+            for status in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                let body = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request).to_lowercase();
+                    if let Some(header_end) = text.find("\r\n\r\n") {
+                        let length: usize = text
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length:"))
+                            .map(|v| v.trim().parse().unwrap())
+                            .unwrap_or(0);
+                        if request.len() >= header_end + 4 + length {
+                            break (text[..header_end].to_string(), request[header_end + 4..].to_vec());
+                        }
+                    }
+                };
+                log.lock().unwrap().push(body);
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let mut config = create_test_config();
+        config.post_url = format!("http://{}/post", addr);
+        config.post_chunk_size = 1;
+        config.post_compress = true;
+        config.post_max_attempts = 2;
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let rows: Vec<EnrichedRow> = ["P1", "P2"]
+            .iter()
+            .map(|part| EnrichedRow {
+                part_no: part.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        enricher.post_enriched_data(&rows).await.unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        let bodies: Vec<String> = received
+            .iter()
+            .map(|(headers, body)| {
+                assert!(headers.contains("content-encoding: gzip"));
+                let mut decoded = String::new();
+                flate2::read::GzDecoder::new(body.as_slice())
+                    .read_to_string(&mut decoded)
+                    .unwrap();
+                urlencoding::decode(&decoded).unwrap().into_owned()
+            })
+            .collect();
+        // The retried attempt resends the same first batch
+        assert_eq!(bodies[0], bodies[1]);
+        assert!(bodies[1].contains("\"part_no\":\"P1\""));
+        assert!(bodies[2].contains("\"part_no\":\"P2\""));
+        assert!(!bodies[2].contains("P1"));
+    }
+
+    #[tokio::test]
+    async fn test_login_page_is_session_expired() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let n = socket.read(&mut buf).await.unwrap();
+            assert!(n > 0);
+            let page = "<!DOCTYPE html><html><body><form>Login</form></body></html>";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                page.len(),
+                page
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let mut config = create_test_config();
+        config.url = format!("http://{}/api/lookup?part=", addr);
+        config.detect_login_html = true;
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let error = enricher
+            .lookup_single_chunk(&["PART001".to_string()])
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("session expired, refresh cookie"));
+
+        assert!(is_html(None, "  <html><head>"));
+        assert!(!is_html(Some("application/json"), "{\"html\": true}"));
+    }
+
+    #[tokio::test]
+    async fn test_lookup_chunks_resumes_from_progress_file() {
+        use tempfile::tempdir;
+
+        let temp_dir = tempdir().unwrap();
+        let progress_path = temp_dir.path().join("progress").join("lookup.json");
+        let mut resolved = HashMap::new();
+        resolved.insert(
+            "PART001".to_string(),
+            LookupResponse {
+                duns: "123456789".to_string(),
+                cof: "US".to_string(),
+                country: "USA".to_string(),
+            },
+        );
+        save_progress(&progress_path, &resolved).unwrap();
+
+        let mut config = create_test_config();
+        // Nothing listens here, so any request would fail the lookup
+        config.url = "http://127.0.0.1:9/lookup".to_string();
+        config.progress_path = Some(progress_path.display().to_string());
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let lookup_data = enricher.lookup_chunks(&["PART001".to_string()]).await.unwrap();
+        assert_eq!(lookup_data["PART001"].duns, "123456789");
+        assert!(!progress_path.exists());
+    }
+
+    #[test]
+    fn test_chunk_pause_with_jitter() {
+        let mut config = create_test_config();
+        assert!(LookupEnricher::new(&config).unwrap().chunk_pause().is_zero());
+
+        config.chunk_delay_ms = 100;
+        config.chunk_jitter_ms = 50;
+        let enricher = LookupEnricher::new(&config).unwrap();
+        for _ in 0..20 {
+            let pause = enricher.chunk_pause();
+            assert!(pause >= Duration::from_millis(100) && pause <= Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn test_redact_for_logging() {
+        let mut config = create_test_config();
+        config.log_redact_fields = vec!["duns".to_string()];
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        assert_eq!(enricher.redact("duns", "123456789"), "****6789");
+        assert_eq!(enricher.redact("duns", "1234"), "****");
+        assert_eq!(enricher.redact("part_no", "P-100200"), "P-100200");
+        assert!(!enricher.logs_raw_content());
+    }
+
+    #[tokio::test]
+    async fn test_redacted_lookup_errors_omit_part_numbers() {
+        let mut config = create_test_config();
+        // Nothing listens here, so the request fails
+        config.url = "http://127.0.0.1:9/lookup?part=".to_string();
+        config.log_redact_fields = vec!["part_no".to_string()];
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let error = enricher
+            .lookup_single_chunk(&["SECRET001".to_string()])
+            .await
+            .err()
+            .unwrap();
+        let message = format!("{:#}", error);
+        assert!(message.contains("http://127.0.0.1:9/lookup?part="));
+        assert!(!message.contains("SECRET001"));
+    }
+
+    #[test]
+    fn test_post_body_jsonl() {
+        let mut config = create_test_config();
+        config.post_format = "jsonl".to_string();
+        let enricher = LookupEnricher::new(&config).unwrap();
+        let rows: Vec<EnrichedRow> = ["P1", "P2"]
+            .iter()
+            .map(|part| EnrichedRow {
+                part_no: part.to_string(),
+                ..Default::default()
+            })
+            .collect();
+
+        let (content_type, body) = enricher.post_body(&rows).unwrap();
+
+        assert_eq!(content_type, "application/x-ndjson");
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for (line, part) in lines.iter().zip(["P1", "P2"]) {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(value["part_no"], part);
+        }
+        assert!(body.ends_with('\n'));
+    }
+
+    #[test]
+    fn test_apply_auth_per_endpoint() {
+        let mut config = create_test_config();
+        config.cookie = "session=shared".to_string();
+        config.post_auth = Some(LookupAuthConfig {
+            kind: "bearer".to_string(),
+            cookie: String::new(),
+            bearer_token: "write-token".to_string(),
+            basic_username: String::new(),
+            basic_password: String::new(),
+        });
+        let enricher = LookupEnricher::new(&config).unwrap();
+
+        let read = enricher
+            .apply_auth(enricher.client.get(&config.url), config.read_auth.as_ref())
+            .build()
+            .unwrap();
+        assert_eq!(read.headers()[header::COOKIE], "session=shared");
+
+        let post = enricher
+            .apply_auth(enricher.client.post(&config.post_url), config.post_auth.as_ref())
+            .build()
+            .unwrap();
+        assert_eq!(post.headers()[header::AUTHORIZATION], "Bearer write-token");
+        assert!(post.headers().get(header::COOKIE).is_none());
+    }
 }
@@ -1,8 +1,10 @@
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
 use dialoguer::{theme::ColorfulTheme, Select};
+use futures::stream::{self, StreamExt};
 use log::{error, info, warn};
-use std::path::PathBuf;
+use rand::Rng;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use tokio::process::Command;
 use tokio::time::{sleep, Duration};
@@ -10,29 +12,42 @@ use tokio::time::{sleep, Duration};
 mod config;
 mod file_utils;
 mod lookup;
+mod state;
+mod summary;
 mod transform;
 mod upload;
 
 use config::Config;
 use file_utils::FileWatcher;
 use lookup::LookupEnricher;
+use summary::{ArchiveAction, FileSummary, RunSummary};
 use transform::Transformer;
-use upload::Uploader;
+use upload::{UploadError, Uploader};
 
 #[derive(Parser)]
 #[command(name = "sap_auto_runner")]
 #[command(about = "Windows-only Rust CLI for running SAP auto extractor and uploading results")]
 struct Cli {
-    /// Path to configuration file
-    #[arg(short, long, default_value = "config.toml")]
+    /// Path to configuration file (falls back to SAP_RUNNER_CONFIG)
+    #[arg(
+        short,
+        long,
+        global = true,
+        env = "SAP_RUNNER_CONFIG",
+        default_value = "config.toml"
+    )]
     config: PathBuf,
 
+    /// Environment overlay: merges config.<ENV>.toml over the base config
+    #[arg(long, global = true)]
+    env: Option<String>,
+
     /// Override API endpoint
     #[arg(long)]
     endpoint: Option<String>,
 
-    /// Override upload mode (multipart or json_base64)
-    #[arg(long, value_parser = ["multipart", "json_base64"])]
+    /// Override upload mode (multipart, json_base64, or resumable)
+    #[arg(long, value_parser = ["multipart", "json_base64", "resumable"])]
     mode: Option<String>,
 
     /// Override output directory
@@ -48,8 +63,69 @@ struct Cli {
     loop_interval: Option<u64>,
 
     /// Enable verbose logging
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "quiet")]
     verbose: bool,
+
+    /// Only log warnings and errors
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Show the interactive menu even when overrides are given
+    #[arg(long, conflicts_with = "no_menu")]
+    force_menu: bool,
+
+    /// Never show the interactive menu
+    #[arg(long)]
+    no_menu: bool,
+
+    /// Enrich the latest file without running the extractor
+    #[arg(long, conflicts_with = "force_menu")]
+    enrich_only: bool,
+
+    /// Skip posting enriched data (writes lookup.output_file instead)
+    #[arg(long)]
+    no_post: bool,
+
+    /// Run the extractor and report its exit code, without processing any files
+    #[arg(long, conflicts_with_all = ["force_menu", "enrich_only"])]
+    extract_only: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Show the most recent run summary
+    Status,
+    /// Print the file glob and every matching file in selection order
+    ListFiles,
+    /// Check that every configured endpoint is reachable and accepts the credentials
+    Doctor,
+    /// Retry the failed files of the most recent failed run, without running the extractor
+    Replay {
+        /// Run summary file to read; defaults to `summary.path`
+        #[arg(long)]
+        summary: Option<PathBuf>,
+    },
+    /// Look up the latest file and write the part numbers that were not found, without posting
+    EnrichReport {
+        /// Where to write the report; `.json` for JSON, anything else for CSV
+        #[arg(long)]
+        output: PathBuf,
+        /// File to look up instead of the newest matching file
+        #[arg(long)]
+        input: Option<PathBuf>,
+    },
+    /// Parse a file with the lookup settings and print its rows and part numbers, without any requests
+    ParseCheck {
+        /// File to parse
+        #[arg(long)]
+        file: PathBuf,
+        /// How many parsed rows to print
+        #[arg(long, default_value_t = 10)]
+        rows: usize,
+    },
 }
 
 #[tokio::main]
@@ -57,27 +133,92 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     // Initialize logging
-    let log_level = if cli.verbose { "debug" } else { "info" };
+    let log_level = if cli.verbose {
+        "debug"
+    } else if cli.quiet {
+        "warn"
+    } else {
+        "info"
+    };
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level)).init();
 
     info!("Starting SAP Auto Runner");
 
     // Load configuration
-    let mut config = Config::load(&cli.config)?;
+    let mut config = Config::load(&cli.config, cli.env.as_deref())?;
+    if let Some(env) = &cli.env {
+        info!("Applied config overlay for environment: {}", env);
+    }
 
-    // Show landing menu if no CLI overrides are provided
+    match &cli.command {
+        Some(Commands::Status) => return show_status(&config),
+        Some(Commands::ListFiles) => return list_files(&config).await,
+        Some(Commands::Doctor) => return doctor(&config).await,
+        Some(Commands::Replay { summary }) => {
+            let summary_path = summary
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(&config.summary.path));
+            return replay(&config, &summary_path).await;
+        }
+        Some(Commands::EnrichReport { output, input }) => {
+            return enrich_report(&config, output, input.as_deref()).await
+        }
+        Some(Commands::ParseCheck { file, rows }) => return parse_check(&config, file, *rows).await,
+        None => {}
+    }
+
+    // Show landing menu if no CLI overrides are provided, unless forced either way;
+    // headless deployments turn it off for good with SAP_RUNNER_NONINTERACTIVE
+    let noninteractive = std::env::var("SAP_RUNNER_NONINTERACTIVE")
+        .is_ok_and(|value| !matches!(value.as_str(), "" | "0" | "false"));
     let no_overrides = cli.endpoint.is_none()
         && cli.mode.is_none()
         && cli.output_dir.is_none()
         && cli.file_glob.is_none()
-        && cli.loop_interval.is_none();
+        && cli.loop_interval.is_none()
+        && !cli.no_post;
+    let show_menu = if noninteractive {
+        false
+    } else if cli.force_menu {
+        true
+    } else if cli.no_menu || cli.enrich_only || cli.extract_only {
+        false
+    } else {
+        no_overrides
+    };
+
+    // Overrides only configure values; the menu below still chooses the action
+    if let Some(endpoint) = cli.endpoint {
+        config.api.endpoint = endpoint;
+    }
+    if let Some(mode) = cli.mode {
+        config.api.mode = mode;
+    }
+    if let Some(output_dir) = cli.output_dir {
+        config.files.output_dir = output_dir.to_string_lossy().to_string();
+    }
+    if let Some(file_glob) = cli.file_glob {
+        config.files.file_glob = file_glob;
+    }
+    if let Some(loop_interval) = cli.loop_interval {
+        config.loop_config.interval_seconds = loop_interval;
+    }
+    if cli.no_post {
+        config.lookup.post = false;
+    }
 
-    let mut menu_selection = None;
-    if no_overrides {
+    let mut menu_selection = if cli.extract_only {
+        Some(4)
+    } else {
+        cli.enrich_only.then_some(2)
+    };
+    if show_menu {
         let items = vec![
             "Run once (no loop)",
             "Run loop (use configured interval)",
             "Enrich latest file only (no extraction)",
+            "Enrich latest file and save locally (no extraction, no post)",
+            "Run extractor only (no file search, transform, or upload)",
             "Open config in Notepad",
             "Exit",
         ];
@@ -86,12 +227,13 @@ async fn main() -> Result<()> {
             .items(&items)
             .default(0)
             .interact()
-            .unwrap_or(3);
+            .unwrap_or(5);
 
         match selection {
             0 => {
                 // Force single run
                 config.loop_config.interval_seconds = 0;
+                config.loop_config.cron = None;
             }
             1 => {
                 // Keep configured loop interval (ensure >0)
@@ -104,6 +246,15 @@ async fn main() -> Result<()> {
                 menu_selection = Some(2);
             }
             3 => {
+                // Same as 2, but only write lookup.output_file for review
+                config.lookup.post = false;
+                menu_selection = Some(2);
+            }
+            4 => {
+                // Extractor only - handle after validation
+                menu_selection = Some(4);
+            }
+            5 => {
                 // Open config in Notepad then exit
                 let _ = std::process::Command::new("notepad")
                     .arg(&cli.config)
@@ -114,36 +265,25 @@ async fn main() -> Result<()> {
         }
     }
 
-    // Apply CLI overrides
-    if let Some(endpoint) = cli.endpoint {
-        config.api.endpoint = endpoint;
-    }
-    if let Some(mode) = cli.mode {
-        config.api.mode = mode;
-    }
-    if let Some(output_dir) = cli.output_dir {
-        config.files.output_dir = output_dir.to_string_lossy().to_string();
-    }
-    if let Some(file_glob) = cli.file_glob {
-        config.files.file_glob = file_glob;
-    }
-    if let Some(loop_interval) = cli.loop_interval {
-        config.loop_config.interval_seconds = loop_interval;
-    }
-
     // Validate configuration
     config.validate()?;
 
     // Check for nested loop conflict
     if config.extraction.subcommand == "run-loop"
-        && config.loop_config.interval_seconds > 0
+        && config.loop_config.is_looping()
         && !config.loop_config.allow_nested
     {
         anyhow::bail!("Error: subcommand is 'run-loop' and loop interval > 0, but allow_nested is false. This would create nested loops.");
     }
 
+    if let Some(4) = menu_selection {
+        return extract_only(&config).await;
+    }
+
     // Create components
-    let file_watcher = FileWatcher::new(&config.files)?.with_archive(&config.archive);
+    let file_watcher = FileWatcher::new(&config.files)?
+        .with_archive(&config.archive)
+        .with_state(config.loop_config.resume_interval)?;
     let transformer = Transformer::new(&config.transform)?;
     let uploader = Uploader::new(&config.api, &config.retry)?;
     let lookup_enricher = if config.lookup.enabled {
@@ -152,22 +292,31 @@ async fn main() -> Result<()> {
         None
     };
 
-    // Handle special menu selections
-    if let Some(selection) = menu_selection {
-        match selection {
-            2 => {
-                // Enrich latest file only (no extraction)
-                return enrich_latest_file_only(&config, &file_watcher, lookup_enricher.as_ref())
-                    .await;
-            }
-            _ => {}
+    // Fail fast rather than after a successful upload
+    file_watcher.check_archive_writable()?;
+
+    // Fail fast on a misconfigured lookup endpoint
+    if let Some(enricher) = &lookup_enricher {
+        if config.lookup.preflight {
+            enricher.preflight().await?;
         }
     }
 
+    // Handle special menu selections
+    if let Some(2) = menu_selection {
+        // Enrich latest file only (no extraction)
+        return enrich_latest_file_only(&config, &file_watcher, lookup_enricher.as_ref()).await;
+    }
+
     // Main execution loop
-    if config.loop_config.interval_seconds == 0 {
+    let schedule = config.loop_config.schedule()?;
+    let mut splay = config.loop_config.startup_splay_secs;
+    if !config.loop_config.is_looping() {
         // Run once
-        run_once(
+        if let Some(splay_secs) = splay {
+            startup_splay(splay_secs).await;
+        }
+        run_and_record(
             &config,
             &file_watcher,
             &transformer,
@@ -177,38 +326,199 @@ async fn main() -> Result<()> {
         .await?;
     } else {
         // Run in loop
+        if config.loop_config.resume_interval && schedule.is_none() {
+            resume_interval(&config, &file_watcher).await;
+        }
+        let pause_file = config.loop_config.pause_file.as_deref().map(Path::new);
+        let mut was_paused = false;
         loop {
-            if let Err(e) = run_once(
-                &config,
-                &file_watcher,
-                &transformer,
-                &uploader,
-                lookup_enricher.as_ref(),
-            )
-            .await
-            {
-                error!("Error in run cycle: {}", e);
+            if let Some(schedule) = &schedule {
+                wait_for_next_fire(schedule, config.loop_config.heartbeat_secs).await;
+            }
+            if let Some(splay_secs) = splay.take() {
+                startup_splay(splay_secs).await;
+            }
+
+            let run_started = tokio::time::Instant::now();
+            let paused_by = pause_file.filter(|path| path.exists());
+            if let Some(path) = paused_by {
+                info!("paused: {} exists, skipping this run", path.display());
+            } else {
+                if was_paused {
+                    info!("Pause file removed, resuming runs");
+                }
+                if let Err(e) = run_and_record(
+                    &config,
+                    &file_watcher,
+                    &transformer,
+                    &uploader,
+                    lookup_enricher.as_ref(),
+                )
+                .await
+                {
+                    error!("Error in run cycle: {:#}", e);
+                }
             }
+            was_paused = paused_by.is_some();
 
+            if schedule.is_none() {
+                let interval = Duration::from_secs(config.loop_config.interval_seconds);
+                if config.loop_config.fixed_rate {
+                    let next = next_fixed_rate_start(run_started, interval);
+                    let wait = next.saturating_duration_since(tokio::time::Instant::now());
+                    info!("Next run in {} seconds (fixed rate)", wait.as_secs());
+                    idle_until(next, config.loop_config.heartbeat_secs).await;
+                } else {
+                    info!(
+                        "Waiting {} seconds before next run",
+                        config.loop_config.interval_seconds
+                    );
+                    let next = tokio::time::Instant::now() + interval;
+                    idle_until(next, config.loop_config.heartbeat_secs).await;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn wait_for_next_fire(schedule: &cron::Schedule, heartbeat_secs: Option<u64>) {
+    match schedule.upcoming(chrono::Local).next() {
+        Some(next) => {
+            let wait = (next - chrono::Local::now())
+                .to_std()
+                .unwrap_or(Duration::ZERO);
             info!(
-                "Waiting {} seconds before next run",
-                config.loop_config.interval_seconds
+                "Next scheduled run at {} (in {} seconds)",
+                next.format("%Y-%m-%d %H:%M:%S"),
+                wait.as_secs()
             );
-            sleep(Duration::from_secs(config.loop_config.interval_seconds)).await;
+            idle_until(tokio::time::Instant::now() + wait, heartbeat_secs).await;
+        }
+        None => {
+            // No future fire times (e.g. a year in the past); avoid spinning
+            warn!("Cron schedule has no upcoming fire times, waiting indefinitely");
+            std::future::pending::<()>().await;
         }
     }
+}
 
-    Ok(())
+/// Sleeps until `deadline`, logging a heartbeat every `heartbeat_secs` so a long
+/// wait between runs doesn't look like a dead process.
+async fn idle_until(deadline: tokio::time::Instant, heartbeat_secs: Option<u64>) {
+    let Some(secs) = heartbeat_secs else {
+        tokio::time::sleep_until(deadline).await;
+        return;
+    };
+    let period = Duration::from_secs(secs);
+    let mut heartbeat = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    let idle = tokio::time::sleep_until(deadline);
+    tokio::pin!(idle);
+
+    loop {
+        tokio::select! {
+            _ = &mut idle => return,
+            _ = heartbeat.tick() => {
+                let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+                info!("idle, next run in {}s", remaining.as_secs());
+            }
+        }
+    }
 }
 
-async fn run_once(
+/// Next slot on the `start + n * interval` grid, skipping any slots the run overran.
+fn next_fixed_rate_start(
+    run_started: tokio::time::Instant,
+    interval: Duration,
+) -> tokio::time::Instant {
+    let now = tokio::time::Instant::now();
+    let mut next = run_started + interval;
+    if next <= now {
+        let skipped = (now - run_started).as_secs_f64() / interval.as_secs_f64();
+        warn!(
+            "Run took longer than the {}s interval; skipping {} missed slot(s)",
+            interval.as_secs(),
+            skipped.floor() as u64
+        );
+        while next <= now {
+            next += interval;
+        }
+    }
+    next
+}
+
+/// Waits out what is left of the interval since the last successful run, so frequent
+/// restarts don't each trigger an extraction.
+async fn resume_interval(config: &Config, file_watcher: &FileWatcher) {
+    let Some(last) = file_watcher.last_run_success() else {
+        return;
+    };
+    let interval = Duration::from_secs(config.loop_config.interval_seconds);
+    // A last run in the future (clock change) counts as just finished
+    let elapsed = (chrono::Utc::now() - last).to_std().unwrap_or(Duration::ZERO);
+    let remaining = interval.saturating_sub(elapsed);
+    if remaining.is_zero() {
+        return;
+    }
+    info!(
+        "Last successful run finished {}s ago; waiting {}s before the first run (loop.resume_interval)",
+        elapsed.as_secs(),
+        remaining.as_secs()
+    );
+    sleep(remaining).await;
+}
+
+/// Sleeps a random 0..=splay_secs so a fleet started at boot doesn't run in lockstep.
+async fn startup_splay(splay_secs: u64) {
+    let delay = rand::thread_rng().gen_range(0..=splay_secs);
+    info!(
+        "Delaying first run by {}s (loop_config.startup_splay_secs = {})",
+        delay, splay_secs
+    );
+    sleep(Duration::from_secs(delay)).await;
+}
+
+async fn run_and_record(
     config: &Config,
     file_watcher: &FileWatcher,
     transformer: &Transformer,
     uploader: &Uploader,
     lookup_enricher: Option<&LookupEnricher>,
 ) -> Result<()> {
-    // Spawn SAP auto process
+    let mut summary = RunSummary::start();
+    let result = run_once(
+        config,
+        file_watcher,
+        transformer,
+        uploader,
+        lookup_enricher,
+        &mut summary,
+    )
+    .await;
+    summary.finish(&result);
+
+    if result.is_ok() && config.loop_config.resume_interval {
+        if let Err(e) = file_watcher.record_run_success() {
+            warn!("Failed to record run in state file: {:#}", e);
+        }
+    }
+
+    if config.summary.enabled {
+        if let Err(e) = summary.append(&config.summary.path) {
+            warn!("Failed to write run summary: {}", e);
+        }
+    }
+
+    result
+}
+
+/// Spawns the SAP auto process and waits for it to exit. With `capture`, its
+/// stdout is written to that file instead of the console.
+async fn run_extractor(
+    config: &Config,
+    capture: Option<&Path>,
+) -> Result<std::process::ExitStatus> {
     info!(
         "Spawning SAP auto process: {} {}",
         config.extraction.executable, config.extraction.subcommand
@@ -217,12 +527,22 @@ async fn run_once(
     let exe_path = std::path::Path::new(&config.extraction.executable);
     let exe_dir = exe_path.parent().unwrap_or(std::path::Path::new("."));
 
+    let stdout = match capture {
+        Some(path) => {
+            info!("Capturing extractor output to {}", path.display());
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Failed to create capture file: {}", path.display()))?;
+            Stdio::from(file)
+        }
+        None => Stdio::inherit(),
+    };
+
     let mut child = Command::new(&config.extraction.executable)
         .arg(&config.extraction.subcommand)
         .args(&config.extraction.args)
         .envs(&config.extraction.env)
         .current_dir(exe_dir)
-        .stdout(Stdio::inherit())
+        .stdout(stdout)
         .stderr(Stdio::inherit())
         .spawn()?;
 
@@ -237,59 +557,463 @@ async fn run_once(
         info!("SAP auto process completed successfully");
     }
 
+    Ok(exit_status)
+}
+
+/// Runs only the extractor, to tell extraction problems from pipeline ones.
+async fn extract_only(config: &Config) -> Result<()> {
+    let exit_status = run_extractor(config, None).await?;
+    match exit_status.code() {
+        Some(code) => println!("Extractor exit code: {}", code),
+        None => println!("Extractor was terminated without an exit code"),
+    }
+
+    if !exit_status.success() {
+        anyhow::bail!("Extractor failed: {}", exit_status);
+    }
+    Ok(())
+}
+
+async fn run_once(
+    config: &Config,
+    file_watcher: &FileWatcher,
+    transformer: &Transformer,
+    uploader: &Uploader,
+    lookup_enricher: Option<&LookupEnricher>,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    // Dropping the folder when the run ends removes a capture that was not archived
+    let capture_dir = if config.extraction.capture_stdout_as_input {
+        Some(capture_dir(config)?)
+    } else {
+        None
+    };
+    let capture = capture_dir.as_ref().map(|dir| {
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+        dir.path().join(format!("extractor_stdout_{}.txt", timestamp))
+    });
+    let exit_status = run_extractor(config, capture.as_deref()).await?;
+    summary.extractor_exit_code = exit_status.code();
+    summary.degraded = !exit_status.success();
+
     // Wait a moment for files to be written
     sleep(Duration::from_millis(500)).await;
 
-    // Find newest file
-    let newest_file = match file_watcher.find_newest_file().await? {
-        Some(file) => {
-            info!("Found newest file: {}", file.display());
-            file
-        }
-        None => {
-            warn!("No matching files found in output directory");
+    // Find newest file, or every matching file when processing all or merging
+    let files = if let Some(capture) = capture {
+        if std::fs::metadata(&capture).map(|m| m.len()).unwrap_or(0) == 0 {
+            warn!("Extractor wrote nothing to stdout");
             return Ok(());
         }
+        vec![capture]
+    } else if config.files.process_all || config.files.merge_matching {
+        file_watcher.find_all_files().await?
+    } else {
+        file_watcher.find_newest_file().await?.into_iter().collect()
     };
+    if files.is_empty() {
+        warn!("No matching files found in output directory");
+        return Ok(());
+    }
+    info!("Found {} file(s) to process", files.len());
 
-    // Wait for file to be stable
-    file_watcher.wait_for_stable_file(&newest_file).await?;
-    info!("File is stable: {}", newest_file.display());
+    process_files(
+        config,
+        file_watcher,
+        transformer,
+        uploader,
+        lookup_enricher,
+        files,
+        summary,
+    )
+    .await
+}
+
+/// A temporary folder in `files.output_dir` for `extraction.capture_stdout_as_input`.
+/// Archiving the capture stays a rename on the same disk, file searches skip the
+/// folder, and it is removed with whatever is left in it when dropped.
+fn capture_dir(config: &Config) -> Result<tempfile::TempDir> {
+    let dir = Path::new(&config.files.output_dir);
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create output directory: {}", dir.display()))?;
+    tempfile::Builder::new()
+        .prefix(file_utils::CAPTURE_DIR_PREFIX)
+        .tempdir_in(dir)
+        .with_context(|| format!("Failed to create capture folder in {}", dir.display()))
+}
+
+/// Delivers already selected files, merged or one by one, into `summary`.
+async fn process_files(
+    config: &Config,
+    file_watcher: &FileWatcher,
+    transformer: &Transformer,
+    uploader: &Uploader,
+    lookup_enricher: Option<&LookupEnricher>,
+    files: Vec<PathBuf>,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    uploader.refresh_bearer_token().await?;
+    uploader.reset_circuit();
+
+    if config.files.merge_matching {
+        let (mut file_summary, result) = process_merged(
+            config,
+            file_watcher,
+            transformer,
+            uploader,
+            lookup_enricher,
+            files,
+        )
+        .await;
+        summary.source_file = Some(file_summary.path.clone());
+        summary.rows = file_summary.rows;
+        summary
+            .archive_actions
+            .append(&mut file_summary.archive_actions);
+        summary.files.push(file_summary);
+        return result;
+    }
+
+    // Files are independent, so one failure doesn't stop the others
+    let outcomes: Vec<(FileSummary, Result<()>)> = stream::iter(files)
+        .map(|file| {
+            process_file(
+                config,
+                file_watcher,
+                transformer,
+                uploader,
+                lookup_enricher,
+                file,
+            )
+        })
+        .buffer_unordered(config.api.upload_concurrency)
+        .collect()
+        .await;
+
+    let total = outcomes.len();
+    let mut errors = Vec::new();
+    for (mut file_summary, result) in outcomes {
+        summary
+            .archive_actions
+            .append(&mut file_summary.archive_actions);
+        if let Err(e) = result {
+            if total > 1 {
+                error!("Failed to process {}: {:#}", file_summary.path, e);
+            }
+            errors.push(e);
+        }
+        summary.files.push(file_summary);
+    }
+
+    if let [file_summary] = summary.files.as_slice() {
+        summary.source_file = Some(file_summary.path.clone());
+        summary.rows = file_summary.rows;
+    }
+
+    match errors.len() {
+        0 => Ok(()),
+        1 if total == 1 => Err(errors.remove(0)),
+        failed => anyhow::bail!("{} of {} files failed to process", failed, total),
+    }
+}
+
+async fn process_file(
+    config: &Config,
+    file_watcher: &FileWatcher,
+    transformer: &Transformer,
+    uploader: &Uploader,
+    lookup_enricher: Option<&LookupEnricher>,
+    file: PathBuf,
+) -> (FileSummary, Result<()>) {
+    info!("Processing file: {}", file.display());
+    let mut file_summary = FileSummary::new(&file);
+
+    let result = async {
+        // Leave the file untouched for the next cycle; the endpoint looks down
+        uploader.check_circuit()?;
+
+        // Wait for file to be stable
+        file_watcher.wait_for_stable_file(&file).await?;
+        info!("File is stable: {}", file.display());
+
+        let hash = file_watcher.tracked_hash(&file).await?;
+        if hash.as_deref().is_some_and(|hash| file_watcher.is_unchanged(hash)) {
+            info!("No new data in {}, skipping", file.display());
+            return Ok(());
+        }
+        let leased = file_watcher.acquire_lease(&file).await?;
+
+        let delivered = deliver_file(
+            config,
+            file_watcher,
+            transformer,
+            uploader,
+            lookup_enricher,
+            &leased,
+            &file.file_name().unwrap().to_string_lossy(),
+            &mut file_summary,
+        )
+        .await;
+        // A validation run must not make skip_if_unchanged skip the real run later
+        let posted = !matches!(delivered, Ok(Delivery::NotPosted));
+
+        let disposed = dispose_file(
+            config,
+            file_watcher,
+            &leased,
+            delivered,
+            &mut file_summary.archive_actions,
+        )
+        .await;
+        if let Err(e) = file_watcher.release_lease(&leased).await {
+            error!("{:#}", e);
+        }
+        if let (Ok(()), Some(hash), true) = (&disposed, hash, posted) {
+            file_watcher.record_processed(&file, hash)?;
+        }
+        disposed
+    }
+    .await;
+
+    file_summary.finish(&result);
+    (file_summary, result)
+}
+
+/// Delivers every matched file as one combined upload, oldest first.
+async fn process_merged(
+    config: &Config,
+    file_watcher: &FileWatcher,
+    transformer: &Transformer,
+    uploader: &Uploader,
+    lookup_enricher: Option<&LookupEnricher>,
+    mut files: Vec<PathBuf>,
+) -> (FileSummary, Result<()>) {
+    // Merge in chronological order, whatever files.process_order says
+    file_watcher.sort_files(&mut files, "oldest_first");
+    let newest = files.last().cloned().unwrap_or_default();
+    let mut file_summary = FileSummary::new(&newest);
+    file_summary.path = format!("{} (merged from {} files)", newest.display(), files.len());
+    info!("Merging {} files into one upload", files.len());
+
+    let mut leased = Vec::new();
+    let result = async {
+        for file in &files {
+            file_watcher.wait_for_stable_file(file).await?;
+            leased.push(file_watcher.acquire_lease(file).await?);
+        }
+        let merged = transformer.merge_files(&leased).await?;
+
+        let delivered = deliver_file(
+            config,
+            file_watcher,
+            transformer,
+            uploader,
+            lookup_enricher,
+            merged.path(),
+            &newest.file_name().unwrap().to_string_lossy(),
+            &mut file_summary,
+        )
+        .await;
+
+        // Every source file shares the outcome of the combined upload. Once it was
+        // delivered, a failed disposal must not stop the others from being archived,
+        // or the next cycle would merge and upload them again
+        let delivered_ok = delivered.is_ok();
+        let mut first_dispose_error = None;
+        for file in &leased {
+            let outcome = match &delivered {
+                Ok(delivery) => Ok(*delivery),
+                Err(_) => Err(anyhow::anyhow!("merged upload failed")),
+            };
+            let disposed = dispose_file(
+                config,
+                file_watcher,
+                file,
+                outcome,
+                &mut file_summary.archive_actions,
+            )
+            .await;
+            match disposed {
+                Err(e) if delivered_ok && first_dispose_error.is_none() => {
+                    first_dispose_error = Some(e)
+                }
+                Err(e) if delivered_ok => error!("{:#}", e),
+                _ => {}
+            }
+        }
+        if let Some(e) = first_dispose_error {
+            return Err(e);
+        }
+        delivered.map(|_| ())
+    }
+    .await;
+
+    // Also runs when a later file could not be claimed
+    for file in &leased {
+        if let Err(e) = file_watcher.release_lease(file).await {
+            error!("{:#}", e);
+        }
+    }
+
+    file_summary.finish(&result);
+    (file_summary, result)
+}
+
+/// How `deliver_file` finished when it did not fail
+#[derive(Debug, Clone, Copy)]
+enum Delivery {
+    Delivered,
+    /// Nothing to upload (`transform.skip_upload_if_empty`); the file stays in place
+    SkippedEmpty,
+    /// Enriched without posting (`lookup.post = false`); the file stays in place
+    NotPosted,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn deliver_file(
+    config: &Config,
+    file_watcher: &FileWatcher,
+    transformer: &Transformer,
+    uploader: &Uploader,
+    lookup_enricher: Option<&LookupEnricher>,
+    newest_file: &std::path::Path,
+    upload_name: &str,
+    file_summary: &mut FileSummary,
+) -> Result<Delivery> {
+    file_watcher.check_size_limit(newest_file).await?;
 
     // Handle lookup enrichment or regular upload
     if config.lookup.enabled && config.api.mode == "lookup_enrich" {
         // Use lookup enrichment flow
         if let Some(enricher) = lookup_enricher {
             info!("Using lookup enrichment flow");
-            let enriched_rows = enricher.enrich_tsv_file(&newest_file).await?;
+            let enrichment = enricher.enrich_tsv_file(newest_file).await?;
+            let enriched_rows = enrichment.rows;
+            file_summary.rows = Some(enriched_rows.len());
+            file_summary.lookup = Some(enrichment.report);
+            file_watcher
+                .verify_row_count(newest_file, enriched_rows.len())
+                .await?;
+            if !config.lookup.post {
+                info!("Lookup enrichment completed; posting disabled by lookup.post");
+                return Ok(Delivery::NotPosted);
+            }
             enricher.post_enriched_data(&enriched_rows).await?;
             info!("Lookup enrichment and upload completed successfully");
         } else {
             anyhow::bail!("Lookup enrichment is enabled but enricher is not available");
         }
     } else {
-        // Use regular transform + upload flow
-        let (upload_file, is_transformed) = if config.transform.enabled {
+        // Use regular transform + upload flow; the temp file lives until the upload is done
+        let transformed = if config.transform.enabled {
             info!("Transforming file before upload");
-            let temp_file = transformer.transform_file(&newest_file).await?;
-            (temp_file.path().to_path_buf(), true)
+            let mut output = transformer.transform_file(newest_file).await?;
+            // The input may be a leased or merged temp file; report the delivered name
+            output.report.source_file = upload_name.to_string();
+            Some(output)
         } else {
-            (newest_file.clone(), false)
+            None
+        };
+        let upload_file = match &transformed {
+            Some(output) => output.file.path().to_path_buf(),
+            None => newest_file.to_path_buf(),
         };
+        file_summary.rows = transformed.as_ref().map(|output| output.data_rows);
+
+        if let Some(output) = transformed.as_ref().filter(|o| !o.rejected_rows.is_empty()) {
+            let rejects_path = std::path::Path::new(&config.files.output_dir)
+                .join(format!("{}.rejects", upload_name));
+            transformer
+                .write_rejects(&output.rejected_rows, &rejects_path)
+                .await?;
+            file_summary.rejected_rows = Some(output.rejected_rows.len());
+        }
+
+        let mut form_fields = Vec::new();
+        if let Some(output) = &transformed {
+            if config.transform.write_report {
+                let report_path = std::path::Path::new(&config.files.output_dir)
+                    .join(format!("{}.report.json", upload_name));
+                transformer
+                    .write_report(&output.report, &report_path)
+                    .await?;
+            }
+            if config.transform.upload_report {
+                let report = serde_json::to_string(&output.report)
+                    .context("Failed to serialize transform report")?;
+                form_fields.push(("transform_report".to_string(), report));
+            }
+        }
+
+        if transformed.as_ref().is_some_and(|output| output.data_rows == 0)
+            && config.transform.skip_upload_if_empty
+        {
+            info!(
+                "No data rows left in {} after transform, skipping upload",
+                upload_name
+            );
+            return Ok(Delivery::SkippedEmpty);
+        }
+
+        if let Some(output) = &transformed {
+            transformer.check_output(output).await?;
+        }
+
+        if config.files.verify_count_sidecar {
+            let row_count = match &transformed {
+                Some(output) => output.data_rows,
+                None => transformer.count_data_rows(newest_file).await?,
+            };
+            file_watcher
+                .verify_row_count(newest_file, row_count)
+                .await?;
+        }
 
         // Upload file
         info!("Uploading file: {}", upload_file.display());
-        uploader
-            .upload_file(
-                &upload_file,
-                &newest_file.file_name().unwrap().to_string_lossy(),
-            )
-            .await?;
+        let captures = file_watcher.name_captures(upload_name);
+        // Transform output is a temp file; the metadata sent is the source file's
+        let uploaded = if transformed.is_some() {
+            uploader
+                .upload_derived_file(
+                    &upload_file,
+                    newest_file,
+                    upload_name,
+                    &form_fields,
+                    &captures,
+                )
+                .await
+        } else {
+            uploader
+                .upload_file(&upload_file, upload_name, &form_fields, &captures)
+                .await
+        };
+        file_summary.add_upload_attempts(&uploaded);
+        uploaded?;
         info!("File uploaded successfully");
 
+        // Send the untouched original too when migrating between importers
+        if transformed.is_some() && config.transform.upload_original_too {
+            info!(
+                "Uploading original file to {}",
+                config.transform.original_endpoint
+            );
+            let uploaded = uploader
+                .upload_file_to(
+                    &config.transform.original_endpoint,
+                    newest_file,
+                    upload_name,
+                    &captures,
+                )
+                .await;
+            file_summary.add_upload_attempts(&uploaded);
+            uploaded.context("Failed to upload original file")?;
+            info!("Original file uploaded successfully");
+        }
+
         // Clean up transformed file if it was created
-        if is_transformed {
-            if let Err(e) = tokio::fs::remove_file(&upload_file).await {
+        if let Some(output) = transformed {
+            if let Err(e) = output.file.close() {
                 warn!(
                     "Failed to clean up transformed file {}: {}",
                     upload_file.display(),
@@ -299,40 +1023,424 @@ async fn run_once(
         }
     }
 
-    // Archive file if enabled
-    if config.archive.enabled {
-        info!("Archiving file");
-        file_watcher.archive_file(&newest_file).await?;
-        info!("File archived");
-    }
-
-    Ok(())
+    Ok(Delivery::Delivered)
 }
 
-async fn enrich_latest_file_only(
+/// Archives a delivered file; otherwise applies `archive.on_failure` and
+/// hands back the delivery error. Where the file ended up is pushed to `actions`.
+async fn dispose_file(
     config: &Config,
     file_watcher: &FileWatcher,
-    lookup_enricher: Option<&LookupEnricher>,
+    file: &std::path::Path,
+    result: Result<Delivery>,
+    actions: &mut Vec<ArchiveAction>,
 ) -> Result<()> {
-    info!("Enriching latest file only (no extraction)");
+    let record_path = file_watcher.unleased_path(file);
+    let left_reason = match result {
+        Ok(Delivery::SkippedEmpty) => Some("nothing to upload"),
+        // Nothing was delivered, so keep the file for review or a later real run
+        Ok(Delivery::NotPosted) => Some("nothing was posted"),
+        _ => None,
+    };
+    if let Some(reason) = left_reason {
+        info!("Leaving {} in place ({})", file.display(), reason);
+        actions.push(ArchiveAction::new(&record_path, "left", &Ok(None)));
+        return Ok(());
+    }
+    let result = result.map(|_| ());
+    if result.is_ok() {
+        if config.archive.enabled {
+            info!("Archiving file");
+            let archived = file_watcher.archive_file(file).await;
+            actions.push(ArchiveAction::new(&record_path, "archived", &archived));
+            archived?;
+            info!("File archived");
+        } else {
+            actions.push(ArchiveAction::new(&record_path, "left", &Ok(None)));
+        }
+        return result;
+    }
+
+    // Skipped rather than failed, so on_failure does not apply
+    let skipped = matches!(
+        result.as_ref().err().and_then(|e| e.downcast_ref()),
+        Some(UploadError::CircuitOpen { .. })
+    );
+    let on_failure = if skipped { "leave" } else { config.archive.on_failure.as_str() };
+    let (action, disposal) = match on_failure {
+        "quarantine" => {
+            warn!("Quarantining file after unsuccessful upload");
+            let quarantined = file_watcher.quarantine_file(file).await.map(Some);
+            ("quarantined", quarantined)
+        }
+        "archive" if config.archive.enabled => {
+            warn!("Archiving file despite unsuccessful upload");
+            ("archived", file_watcher.archive_file(file).await)
+        }
+        _ => {
+            info!("Leaving {} in place to retry next cycle", file.display());
+            ("left", Ok(None))
+        }
+    };
+    actions.push(ArchiveAction::new(&record_path, action, &disposal));
+    if let Err(e) = disposal {
+        error!("Failed to handle file after unsuccessful upload: {:#}", e);
+    }
 
-    // Check if output directory exists
-    let output_dir = std::path::Path::new(&config.files.output_dir);
-    if !output_dir.exists() {
+    result
+}
+
+fn show_status(config: &Config) -> Result<()> {
+    let path = std::path::Path::new(&config.summary.path);
+    if !path.exists() {
         anyhow::bail!(
-            "Output directory does not exist: {}\nPlease check your configuration or run the extraction first.",
-            output_dir.display()
+            "No run summary found at {}\nEnable [summary] in your config to record runs.",
+            path.display()
+        );
+    }
+
+    let last = match RunSummary::read_last(path)? {
+        Some(last) => last,
+        None => {
+            println!("No runs recorded yet in {}", path.display());
+            return Ok(());
+        }
+    };
+
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let rows = [
+        ("Started", last.started_at.to_rfc3339()),
+        ("Finished", or_dash(last.finished_at.map(|t| t.to_rfc3339()))),
+        ("Duration", format!("{} ms", last.duration_ms)),
+        (
+            "Result",
+            match (last.success, last.degraded) {
+                (true, false) => "success",
+                (true, true) => "success (degraded: extractor exited non-zero)",
+                (false, _) => "FAILED",
+            }
+            .to_string(),
+        ),
+        ("Source file", or_dash(last.source_file)),
+        ("Rows", or_dash(last.rows.map(|r| r.to_string()))),
+        (
+            "Extractor exit",
+            or_dash(last.extractor_exit_code.map(|c| c.to_string())),
+        ),
+        (
+            "Files",
+            format!(
+                "{} ({} failed)",
+                last.files.len(),
+                last.files.iter().filter(|f| !f.success).count()
+            ),
+        ),
+        (
+            "Lookup hit rate",
+            or_dash(lookup_hit_rate(&last.files).map(|rate| format!("{:.1}%", rate * 100.0))),
+        ),
+        ("Error", or_dash(last.error)),
+    ];
+
+    println!("Last run ({})", path.display());
+    for (label, value) in rows {
+        println!("  {:<16} {}", label, value);
+    }
+
+    if !last.archive_actions.is_empty() {
+        println!("Archive actions");
+        for action in &last.archive_actions {
+            let outcome = match (&action.destination, &action.error) {
+                (_, Some(error)) => format!("failed: {}", error),
+                (Some(destination), None) => destination.clone(),
+                (None, None) => "-".to_string(),
+            };
+            println!("  {:<12} {} -> {}", action.action, action.file, outcome);
+        }
+    }
+
+    Ok(())
+}
+
+/// Combined hit rate across every file that went through lookup.
+fn lookup_hit_rate(files: &[FileSummary]) -> Option<f64> {
+    let (found, queried) = files
+        .iter()
+        .filter_map(|file| file.lookup.as_ref())
+        .fold((0, 0), |(found, queried), report| {
+            (found + report.parts_found, queried + report.unique_parts)
+        });
+    (queried > 0).then(|| found as f64 / queried as f64)
+}
+
+async fn list_files(config: &Config) -> Result<()> {
+    let file_watcher = FileWatcher::new(&config.files)?;
+    if config.files.glob_is_regex {
+        println!("Regex: {} (file names in {})", config.files.file_glob, config.files.output_dir);
+    } else {
+        println!("Pattern: {}", file_watcher.glob_pattern());
+    }
+
+    let mut files = file_watcher.find_all_files().await?;
+    if !config.files.process_all {
+        file_watcher.sort_files(&mut files, "newest_first");
+    }
+    if files.is_empty() {
+        println!("No matching files");
+        return Ok(());
+    }
+
+    let format_time = |time: std::time::SystemTime| {
+        chrono::DateTime::<chrono::Local>::from(time)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    };
+
+    // Processing order with process_all; otherwise newest first, so the first
+    // entry is the one a run would pick
+    for (i, file) in files.iter().enumerate() {
+        let metadata = std::fs::metadata(file)?;
+        let mtime = metadata.modified().map(format_time).unwrap_or_else(|_| "-".to_string());
+        let selected = i == 0 || config.files.process_all || config.files.merge_matching;
+        let mut line = format!(
+            "{} {}  {:>12} bytes  {}",
+            if selected { "*" } else { " " },
+            mtime,
+            metadata.len(),
+            file.display()
         );
+        if let Some(timestamp) = file_watcher.filename_timestamp(file) {
+            // Prefix timestamps are parsed as written, without a timezone
+            let prefix = chrono::DateTime::<chrono::Utc>::from(timestamp);
+            line.push_str(&format!("  (prefix {})", prefix.format("%Y-%m-%d %H:%M:%S")));
+        }
+        println!("{}", line);
     }
 
-    if !output_dir.is_dir() {
+    println!(
+        "\n{} matching file(s); * marks what the next run would process",
+        files.len()
+    );
+    Ok(())
+}
+
+async fn replay(config: &Config, summary_path: &std::path::Path) -> Result<()> {
+    let failed_run = RunSummary::read_last_failed(summary_path)?.with_context(|| {
+        format!("No failed run recorded in {}", summary_path.display())
+    })?;
+    info!(
+        "Replaying the failed run started at {}",
+        failed_run.started_at.to_rfc3339()
+    );
+
+    if failed_run.files.is_empty() {
         anyhow::bail!(
-            "Output path is not a directory: {}\nPlease check your configuration.",
-            output_dir.display()
+            "The failed run did not reach any file ({}); nothing to replay",
+            failed_run.error.as_deref().unwrap_or("no error recorded")
+        );
+    }
+
+    let mut files = Vec::new();
+    for file_summary in failed_run.files.iter().filter(|f| !f.success) {
+        let path = PathBuf::from(&file_summary.path);
+        if path.is_file() {
+            files.push(path);
+        } else {
+            // Archived, quarantined, or a merged upload, whose summary path is not a file
+            warn!("Skipping {}: it no longer exists", file_summary.path);
+        }
+    }
+    if files.is_empty() {
+        anyhow::bail!("None of the failed files of that run still exist");
+    }
+
+    let file_watcher = FileWatcher::new(&config.files)?
+        .with_archive(&config.archive)
+        .with_state(config.loop_config.resume_interval)?;
+    let transformer = Transformer::new(&config.transform)?;
+    let uploader = Uploader::new(&config.api, &config.retry)?;
+    let lookup_enricher = if config.lookup.enabled {
+        Some(LookupEnricher::new(&config.lookup)?)
+    } else {
+        None
+    };
+    file_watcher.check_archive_writable()?;
+
+    let mut summary = RunSummary::start();
+    let result = process_files(
+        config,
+        &file_watcher,
+        &transformer,
+        &uploader,
+        lookup_enricher.as_ref(),
+        files,
+        &mut summary,
+    )
+    .await;
+    summary.finish(&result);
+
+    if config.summary.enabled {
+        if let Err(e) = summary.append(&config.summary.path) {
+            warn!("Failed to write run summary: {}", e);
+        }
+    }
+
+    result
+}
+
+async fn enrich_report(
+    config: &Config,
+    output: &std::path::Path,
+    input: Option<&std::path::Path>,
+) -> Result<()> {
+    if !config.lookup.enabled {
+        anyhow::bail!("enrich-report requires lookup.enabled = true");
+    }
+
+    let file = match input {
+        Some(path) => path.to_path_buf(),
+        None => FileWatcher::new(&config.files)?
+            .find_newest_file()
+            .await?
+            .context("No matching files to look up")?,
+    };
+
+    let enricher = LookupEnricher::new(&config.lookup)?;
+    let enrichment = enricher.enrich_rows(&file).await?;
+    enricher
+        .write_unmatched(&enrichment.unmatched, output)
+        .await?;
+
+    let report = &enrichment.report;
+    println!("File: {}", file.display());
+    println!(
+        "{} rows, {} unique parts queried, {} not found",
+        report.total_rows, report.unique_parts, report.parts_not_found
+    );
+    println!(
+        "Wrote {} unmatched rows to {}",
+        enrichment.unmatched.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+async fn parse_check(config: &Config, file: &std::path::Path, show_rows: usize) -> Result<()> {
+    let enricher = LookupEnricher::new(&config.lookup)?;
+    let (rows, part_numbers) = enricher.parse_check(file).await?;
+
+    println!("File: {}", file.display());
+    println!("{} rows parsed", rows.len());
+    if !rows.is_empty() {
+        println!("{:<12} {:<14} Part", "Plant", "Delivery");
+        for row in rows.iter().take(show_rows) {
+            println!("{:<12} {:<14} {}", row.plant, row.delivery, row.part_no);
+        }
+        if rows.len() > show_rows {
+            println!("... and {} more rows", rows.len() - show_rows);
+        }
+    }
+
+    println!("{} unique part numbers", part_numbers.len());
+    for part in &part_numbers {
+        println!("  {}", part);
+    }
+    Ok(())
+}
+
+struct EndpointCheck {
+    name: &'static str,
+    url: String,
+    status: Result<reqwest::StatusCode>,
+    /// The dummy lookup must succeed; HEAD/OPTIONS probes only need to get past auth
+    require_success: bool,
+}
+
+impl EndpointCheck {
+    fn passed(&self) -> bool {
+        match &self.status {
+            Ok(status) if self.require_success => status.is_success(),
+            Ok(status) => !matches!(status.as_u16(), 401 | 403 | 404) && !status.is_server_error(),
+            Err(_) => false,
+        }
+    }
+}
+
+async fn doctor(config: &Config) -> Result<()> {
+    let mut checks = Vec::new();
+
+    // File mode writes to a local directory; there is no upload endpoint to check
+    if !(config.lookup.enabled && config.api.mode == "lookup_enrich") && config.api.mode != "file" {
+        let uploader = Uploader::new(&config.api, &config.retry)?;
+        let mut endpoints = vec![("upload", &config.api.endpoint)];
+        if let Some(fallback) = &config.api.fallback_endpoint {
+            endpoints.push(("upload fallback", fallback));
+        }
+        if config.transform.enabled && config.transform.upload_original_too {
+            endpoints.push(("upload original", &config.transform.original_endpoint));
+        }
+        for (name, url) in endpoints {
+            checks.push(EndpointCheck {
+                name,
+                url: url.clone(),
+                status: uploader.probe(url).await,
+                require_success: false,
+            });
+        }
+    }
+
+    if config.lookup.enabled {
+        let enricher = LookupEnricher::new(&config.lookup)?;
+        checks.push(EndpointCheck {
+            name: "lookup",
+            url: config.lookup.url.clone(),
+            status: enricher.probe_lookup().await,
+            require_success: true,
+        });
+        if config.lookup.post {
+            checks.push(EndpointCheck {
+                name: "lookup post",
+                url: config.lookup.post_url.clone(),
+                status: enricher.probe_post().await,
+                require_success: false,
+            });
+        }
+    }
+
+    println!("{:<16} {:<6} {:<6} URL", "Endpoint", "Result", "Status");
+    for check in &checks {
+        let status = match &check.status {
+            Ok(status) => status.as_u16().to_string(),
+            Err(_) => "-".to_string(),
+        };
+        println!(
+            "{:<16} {:<6} {:<6} {}",
+            check.name,
+            if check.passed() { "PASS" } else { "FAIL" },
+            status,
+            check.url
         );
+        if let Err(e) = &check.status {
+            println!("  {:#}", e);
+        }
+    }
+
+    let failed = checks.iter().filter(|check| !check.passed()).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} endpoint checks failed", failed, checks.len());
     }
+    println!("\nAll {} endpoint checks passed", checks.len());
+    Ok(())
+}
 
-    // Find newest file
+async fn enrich_latest_file_only(
+    config: &Config,
+    file_watcher: &FileWatcher,
+    lookup_enricher: Option<&LookupEnricher>,
+) -> Result<()> {
+    info!("Enriching latest file only (no extraction)");
+
+    // Find newest file (also checks that the output directory exists)
     let newest_file = match file_watcher.find_newest_file().await? {
         Some(file) => {
             info!("Found newest file: {}", file.display());
@@ -381,26 +1489,103 @@ async fn enrich_latest_file_only(
         );
     }
 
-    if let Some(enricher) = lookup_enricher {
-        info!(
-            "Starting lookup enrichment for file: {}",
-            newest_file.display()
-        );
-        let enriched_rows = enricher.enrich_tsv_file(&newest_file).await?;
+    let enricher = match lookup_enricher {
+        Some(enricher) => enricher,
+        None => anyhow::bail!(
+            "Lookup enrichment is enabled but enricher is not available.\nThis is an internal error - please check your configuration."
+        ),
+    };
+
+    info!(
+        "Starting lookup enrichment for file: {}",
+        newest_file.display()
+    );
+    let result: Result<Delivery> = async {
+        let enriched_rows = enricher.enrich_tsv_file(&newest_file).await?.rows;
+        file_watcher
+            .verify_row_count(&newest_file, enriched_rows.len())
+            .await?;
+        if !config.lookup.post {
+            info!("Lookup enrichment completed; posting disabled by lookup.post");
+            return Ok(Delivery::NotPosted);
+        }
         enricher.post_enriched_data(&enriched_rows).await?;
         info!("Lookup enrichment and upload completed successfully");
-    } else {
-        anyhow::bail!(
-            "Lookup enrichment is enabled but enricher is not available.\nThis is an internal error - please check your configuration."
-        );
+        Ok(Delivery::Delivered)
     }
+    .await;
+
+    dispose_file(config, file_watcher, &newest_file, result, &mut Vec::new()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_dispose_file_leaves_unposted_file() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.archive.enabled = true;
+        config.archive.path = temp_dir.path().join("archive").display().to_string();
+        let file_watcher = FileWatcher::new(&config.files)
+            .unwrap()
+            .with_archive(&config.archive);
+
+        let unposted = temp_dir.path().join("unposted.txt");
+        std::fs::write(&unposted, "Plant\tDelivery\tMaterial\n").unwrap();
+        let mut actions = Vec::new();
+        dispose_file(
+            &config,
+            &file_watcher,
+            &unposted,
+            Ok(Delivery::NotPosted),
+            &mut actions,
+        )
+        .await
+        .unwrap();
+        assert!(unposted.exists());
+        assert_eq!(actions[0].action, "left");
 
-    // Archive file if enabled
-    if config.archive.enabled {
-        info!("Archiving file");
-        file_watcher.archive_file(&newest_file).await?;
-        info!("File archived");
+        let delivered = temp_dir.path().join("delivered.txt");
+        std::fs::write(&delivered, "Plant\tDelivery\tMaterial\n").unwrap();
+        dispose_file(
+            &config,
+            &file_watcher,
+            &delivered,
+            Ok(Delivery::Delivered),
+            &mut actions,
+        )
+        .await
+        .unwrap();
+        assert!(!delivered.exists());
+        assert_eq!(actions[1].action, "archived");
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn test_capture_is_removed_when_extractor_fails_to_spawn() {
+        let temp_dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.extraction.executable = temp_dir.path().join("missing.exe").display().to_string();
+        config.extraction.capture_stdout_as_input = true;
+        config.files.output_dir = temp_dir.path().join("outputs").display().to_string();
+        config.api.auth = "none".to_string();
+        let file_watcher = FileWatcher::new(&config.files).unwrap();
+        let transformer = Transformer::new(&config.transform).unwrap();
+        let uploader = Uploader::new(&config.api, &config.retry).unwrap();
+
+        let result = run_once(
+            &config,
+            &file_watcher,
+            &transformer,
+            &uploader,
+            None,
+            &mut RunSummary::start(),
+        )
+        .await;
+        assert!(result.is_err());
+        let left = std::fs::read_dir(&config.files.output_dir).unwrap().count();
+        assert_eq!(left, 0);
+    }
 }
@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A file that was delivered successfully
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessedFile {
+    pub file: String,
+    /// Hex SHA-256 of the file content
+    pub hash: String,
+    pub processed_at: DateTime<Utc>,
+}
+
+/// What the runner remembers between runs and restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunState {
+    /// Oldest first
+    #[serde(default)]
+    pub processed: Vec<ProcessedFile>,
+    /// When the last successful run finished, for `loop.resume_interval`
+    #[serde(default)]
+    pub last_success_at: Option<DateTime<Utc>>,
+}
+
+impl RunState {
+    /// A missing state file is an empty state.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read state file: {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse state file: {}", path.display()))
+    }
+
+    /// Writes through a temporary file so a crash never leaves a truncated state.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let parent = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create state directory: {}", parent.display()))?;
+
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize run state")?;
+        let mut temp = tempfile::NamedTempFile::new_in(parent)
+            .with_context(|| format!("Failed to create state file in {}", parent.display()))?;
+        std::io::Write::write_all(&mut temp, json.as_bytes())
+            .with_context(|| format!("Failed to write state file: {}", path.display()))?;
+        temp.persist(path)
+            .with_context(|| format!("Failed to replace state file: {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn last_hash(&self) -> Option<&str> {
+        self.processed.last().map(|entry| entry.hash.as_str())
+    }
+
+    /// Drops the oldest processed entries beyond `max_entries` and those recorded
+    /// before `cutoff`.
+    fn prune(&mut self, max_entries: usize, cutoff: Option<DateTime<Utc>>) {
+        self.processed.sort_by_key(|entry| entry.processed_at);
+        if let Some(cutoff) = cutoff {
+            self.processed.retain(|entry| entry.processed_at >= cutoff);
+        }
+        if max_entries > 0 && self.processed.len() > max_entries {
+            let excess = self.processed.len() - max_entries;
+            self.processed.drain(..excess);
+        }
+    }
+}
+
+/// The run state shared by concurrent file tasks, saved after every change.
+pub struct StateStore {
+    path: PathBuf,
+    state: Mutex<RunState>,
+    /// 0 keeps every entry
+    max_entries: usize,
+    /// 0 keeps every entry
+    max_age_days: u64,
+}
+
+impl StateStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let state = RunState::load(&path)?;
+        Ok(Self {
+            path,
+            state: Mutex::new(state),
+            max_entries: 0,
+            max_age_days: 0,
+        })
+    }
+
+    /// Limits the processed files kept, applied each time one is recorded.
+    pub fn with_retention(mut self, max_entries: usize, max_age_days: u64) -> Self {
+        self.max_entries = max_entries;
+        self.max_age_days = max_age_days;
+        self
+    }
+
+    pub fn last_hash(&self) -> Option<String> {
+        self.state.lock().unwrap().last_hash().map(str::to_string)
+    }
+
+    pub fn last_success_at(&self) -> Option<DateTime<Utc>> {
+        self.state.lock().unwrap().last_success_at
+    }
+
+    pub fn record_success(&self, at: DateTime<Utc>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.last_success_at = Some(at);
+        state.save(&self.path)
+    }
+
+    pub fn record_processed(&self, file: &Path, hash: String) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.processed.push(ProcessedFile {
+            file: file.display().to_string(),
+            hash,
+            processed_at: Utc::now(),
+        });
+        let cutoff = (self.max_age_days > 0)
+            .then(|| Utc::now() - chrono::Duration::days(self.max_age_days as i64));
+        state.prune(self.max_entries, cutoff);
+        state.save(&self.path)
+    }
+}
+
+/// Hex SHA-256 of a file's content.
+pub async fn content_hash(path: &Path) -> Result<String> {
+    let content = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read file for hashing: {}", path.display()))?;
+    Ok(format!("{:x}", Sha256::digest(&content)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_state_round_trip() {
+        let temp_dir = tempdir().unwrap();
+        let state_path = temp_dir.path().join("state").join("runner_state.json");
+        let file = temp_dir.path().join("report.txt");
+        std::fs::write(&file, "Plant\tDelivery\n").unwrap();
+
+        let store = StateStore::open(&state_path).unwrap();
+        assert!(store.last_hash().is_none());
+
+        let hash = content_hash(&file).await.unwrap();
+        assert_eq!(hash.len(), 64);
+        store.record_processed(&file, hash.clone()).unwrap();
+
+        let reopened = StateStore::open(&state_path).unwrap();
+        assert_eq!(reopened.last_hash(), Some(hash.clone()));
+        assert!(reopened.last_success_at().is_none());
+
+        let finished = Utc::now();
+        reopened.record_success(finished).unwrap();
+        let reopened = StateStore::open(&state_path).unwrap();
+        assert_eq!(reopened.last_success_at(), Some(finished));
+        assert_eq!(reopened.last_hash(), Some(hash));
+    }
+
+    #[test]
+    fn test_state_retention_prunes_oldest() {
+        let temp_dir = tempdir().unwrap();
+        let state_path = temp_dir.path().join("runner_state.json");
+
+        let stale = ProcessedFile {
+            file: "stale.txt".to_string(),
+            hash: "0".repeat(64),
+            processed_at: Utc::now() - chrono::Duration::days(40),
+        };
+        RunState {
+            processed: vec![stale],
+            last_success_at: None,
+        }
+        .save(&state_path)
+        .unwrap();
+
+        let store = StateStore::open(&state_path).unwrap().with_retention(2, 30);
+        for (i, name) in ["a.txt", "b.txt", "c.txt"].iter().enumerate() {
+            store
+                .record_processed(Path::new(name), i.to_string())
+                .unwrap();
+        }
+
+        let state = RunState::load(&state_path).unwrap();
+        let files: Vec<&str> = state.processed.iter().map(|e| e.file.as_str()).collect();
+        assert_eq!(files, ["b.txt", "c.txt"]);
+        assert_eq!(state.last_hash(), Some("2"));
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::lookup::LookupReport;
+use crate::upload::{UploadAttempt, UploadAttempts};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: u64,
+    pub success: bool,
+    pub source_file: Option<String>,
+    pub extractor_exit_code: Option<i32>,
+    /// The extractor exited non-zero, whether or not the upload then succeeded
+    #[serde(default)]
+    pub degraded: bool,
+    pub rows: Option<usize>,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub files: Vec<FileSummary>,
+    /// Where each processed file ended up
+    #[serde(default)]
+    pub archive_actions: Vec<ArchiveAction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveAction {
+    pub file: String,
+    /// "archived", "quarantined", or "left"
+    pub action: String,
+    pub destination: Option<String>,
+    pub error: Option<String>,
+}
+
+impl ArchiveAction {
+    pub fn new(file: &Path, action: &str, result: &Result<Option<PathBuf>>) -> Self {
+        Self {
+            file: file.display().to_string(),
+            action: action.to_string(),
+            destination: result
+                .as_ref()
+                .ok()
+                .and_then(|dest| dest.as_ref())
+                .map(|dest| dest.display().to_string()),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSummary {
+    pub path: String,
+    pub rows: Option<usize>,
+    pub success: bool,
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lookup: Option<LookupReport>,
+    /// Rows moved to the reject file by `transform.min_columns`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rejected_rows: Option<usize>,
+    /// Sent with each upload attempt, for tracing in the gateway logs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub correlation_ids: Vec<String>,
+    /// Bytes sent and time taken by each upload attempt
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upload_attempts: Vec<UploadAttempt>,
+    /// Collected into `RunSummary::archive_actions`
+    #[serde(skip)]
+    pub archive_actions: Vec<ArchiveAction>,
+}
+
+impl FileSummary {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.display().to_string(),
+            rows: None,
+            success: false,
+            error: None,
+            lookup: None,
+            rejected_rows: None,
+            correlation_ids: Vec::new(),
+            upload_attempts: Vec::new(),
+            archive_actions: Vec::new(),
+        }
+    }
+
+    /// Keeps the attempts of an upload, whether it succeeded or failed.
+    pub fn add_upload_attempts(&mut self, result: &Result<UploadAttempts>) {
+        let attempts = match result {
+            Ok(attempts) => Some(attempts),
+            Err(e) => e.downcast_ref::<UploadAttempts>(),
+        };
+        if let Some(attempts) = attempts {
+            self.correlation_ids
+                .extend(attempts.correlation_ids().map(str::to_string));
+            self.upload_attempts.extend(attempts.0.iter().cloned());
+        }
+    }
+
+    pub fn finish(&mut self, result: &Result<()>) {
+        self.success = result.is_ok();
+        self.error = result.as_ref().err().map(|e| format!("{:#}", e));
+    }
+}
+
+impl RunSummary {
+    pub fn start() -> Self {
+        Self {
+            started_at: Utc::now(),
+            finished_at: None,
+            duration_ms: 0,
+            success: false,
+            source_file: None,
+            extractor_exit_code: None,
+            degraded: false,
+            rows: None,
+            error: None,
+            files: Vec::new(),
+            archive_actions: Vec::new(),
+        }
+    }
+
+    pub fn finish(&mut self, result: &Result<()>) {
+        let now = Utc::now();
+        self.finished_at = Some(now);
+        self.duration_ms = (now - self.started_at).num_milliseconds().max(0) as u64;
+        self.success = result.is_ok();
+        self.error = result.as_ref().err().map(|e| format!("{:#}", e));
+    }
+
+    /// Appends this summary as a single JSON line.
+    pub fn append<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create summary directory: {}", parent.display())
+            })?;
+        }
+
+        let line = serde_json::to_string(self).context("Failed to serialize run summary")?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open summary file: {}", path.display()))?;
+        writeln!(file, "{}", line)
+            .with_context(|| format!("Failed to write summary file: {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Reads the most recent complete summary line, skipping a partially written tail.
+    pub fn read_last<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        Self::read_last_matching(path, |_| true)
+    }
+
+    /// Reads the most recent run that did not succeed.
+    pub fn read_last_failed<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        Self::read_last_matching(path, |summary| !summary.success)
+    }
+
+    fn read_last_matching<P: AsRef<Path>>(
+        path: P,
+        keep: impl Fn(&Self) -> bool,
+    ) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read summary file: {}", path.display()))?;
+
+        Ok(content
+            .lines()
+            .rev()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .find(|summary| keep(summary)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_append_and_read_last() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("summary.jsonl");
+
+        let mut first = RunSummary::start();
+        first.source_file = Some("first.txt".to_string());
+        first.finish(&Ok(()));
+        first.append(&path).unwrap();
+
+        let mut second = RunSummary::start();
+        second.source_file = Some("second.txt".to_string());
+        second.degraded = true;
+        second.finish(&Err(anyhow::anyhow!("upload failed")));
+        second.append(&path).unwrap();
+
+        let last = RunSummary::read_last(&path).unwrap().unwrap();
+        assert_eq!(last.source_file.as_deref(), Some("second.txt"));
+        assert!(!last.success);
+        assert!(last.degraded);
+        assert_eq!(last.error.as_deref(), Some("upload failed"));
+    }
+
+    #[test]
+    fn test_read_last_failed() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("summary.jsonl");
+
+        for (source, result) in [
+            ("first.txt", Err(anyhow::anyhow!("upload failed"))),
+            ("second.txt", Ok(())),
+        ] {
+            let mut summary = RunSummary::start();
+            summary.source_file = Some(source.to_string());
+            summary.finish(&result);
+            summary.append(&path).unwrap();
+        }
+
+        let failed = RunSummary::read_last_failed(&path).unwrap().unwrap();
+        assert_eq!(failed.source_file.as_deref(), Some("first.txt"));
+    }
+}
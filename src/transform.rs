@@ -1,29 +1,147 @@
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use encoding_rs::WINDOWS_1252;
 use log::{debug, info, warn};
+use regex::Regex;
+use serde::Serialize;
+use std::borrow::Cow;
 use std::collections::HashSet;
 use std::io::Write;
 use std::path::Path;
 use tempfile::NamedTempFile;
 
-use crate::config::TransformConfig;
+use crate::config::{ColumnRule, TransformConfig};
+use crate::file_utils::read_input_bytes;
+use crate::state::content_hash;
+
+struct CompiledRule {
+    rule: ColumnRule,
+    regex: Option<Regex>,
+}
+
+/// A parsed `transform.row_ops` entry
+#[derive(Debug, PartialEq)]
+enum RowOp {
+    Uppercase(usize),
+    Trim(usize),
+    /// Left-pads a non-empty cell with zeros to the given width
+    ZeroPad(usize, usize),
+}
+
+impl RowOp {
+    fn parse(spec: &str) -> Result<Self> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let number = |part: &str, what: &str| {
+            part.trim()
+                .parse::<usize>()
+                .with_context(|| format!("Invalid {} in transform.row_ops entry '{}'", what, spec))
+        };
+        match parts.as_slice() {
+            ["uppercase", column] => Ok(RowOp::Uppercase(number(column, "column")?)),
+            ["trim", column] => Ok(RowOp::Trim(number(column, "column")?)),
+            ["zeropad", column, width] => Ok(RowOp::ZeroPad(
+                number(column, "column")?,
+                number(width, "width")?,
+            )),
+            _ => anyhow::bail!(
+                "Invalid transform.row_ops entry '{}'; expected uppercase:<column>, \
+                 trim:<column>, or zeropad:<column>:<width>",
+                spec
+            ),
+        }
+    }
+
+    fn apply(&self, cells: &mut [String]) {
+        let column = match *self {
+            RowOp::Uppercase(column) | RowOp::Trim(column) | RowOp::ZeroPad(column, _) => column,
+        };
+        // Rows without this column are left as they are
+        if let Some(cell) = cells.get_mut(column) {
+            match *self {
+                RowOp::Uppercase(_) => *cell = cell.to_uppercase(),
+                RowOp::Trim(_) => *cell = cell.trim().to_string(),
+                RowOp::ZeroPad(_, width) if !cell.is_empty() => {
+                    *cell = format!("{:0>width$}", cell, width = width)
+                }
+                RowOp::ZeroPad(..) => {}
+            }
+        }
+    }
+}
 
 pub struct Transformer {
     config: TransformConfig,
+    rules: Vec<CompiledRule>,
+    row_ops: Vec<RowOp>,
+}
+
+pub struct TransformOutput {
+    pub file: NamedTempFile,
+    pub data_rows: usize,
+    /// Raw rows below `min_columns`, left out of `file`
+    pub rejected_rows: Vec<String>,
+    pub report: TransformReport,
+}
+
+/// What a transform did, for the `.report.json` sidecar
+#[derive(Debug, Clone, Serialize)]
+pub struct TransformReport {
+    pub source_file: String,
+    pub generated_at: DateTime<Utc>,
+    pub input_lines: usize,
+    pub data_rows: usize,
+    /// Left out by `dedupe_rows`
+    pub duplicate_rows: usize,
+    /// Rows that broke a validation rule, whether kept or dropped
+    pub invalid_rows: usize,
+    pub rejected_rows: usize,
+    pub incomplete_last_line_dropped: bool,
+    /// "utf-8" or "windows-1252"
+    pub encoding: String,
+    /// Hex SHA-256 of the transformed output
+    pub sha256: String,
 }
 
 impl Transformer {
     pub fn new(config: &TransformConfig) -> Result<Self> {
+        let rules = config
+            .validation
+            .rules
+            .iter()
+            .map(|rule| {
+                let regex = rule
+                    .regex
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .with_context(|| {
+                        format!("Invalid validation regex for column {}", rule.column)
+                    })?;
+                Ok(CompiledRule {
+                    rule: rule.clone(),
+                    regex,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let row_ops = config
+            .row_ops
+            .iter()
+            .map(|spec| RowOp::parse(spec))
+            .collect::<Result<Vec<_>>>()?;
+
         Ok(Self {
             config: config.clone(),
+            rules,
+            row_ops,
         })
     }
 
-    pub async fn transform_file(&self, input_path: &Path) -> Result<NamedTempFile> {
+    pub async fn transform_file(&self, input_path: &Path) -> Result<TransformOutput> {
         info!("Transforming file: {}", input_path.display());
 
         // Read file content
-        let content = self.read_file_content(input_path).await?;
+        let (content, encoding) = self.read_file_content(input_path).await?;
         debug!("Read {} bytes from file", content.len());
 
         // Parse lines
@@ -42,11 +160,29 @@ impl Transformer {
         let data_start = self.find_data_start(&lines)?;
         debug!("Data starts at line {}", data_start + 1);
 
+        let mut data_end = lines.len();
+        if self.config.drop_incomplete_last_line
+            && !content.ends_with('\n')
+            && data_end > data_start
+            && !lines[data_end - 1].trim().is_empty()
+        {
+            warn!(
+                "Dropping line {} because it has no terminating newline (possibly still being written)",
+                data_end
+            );
+            data_end -= 1;
+        }
+        let incomplete_last_line_dropped = data_end < lines.len();
+
         // Extract and process data rows
         let mut data_rows = Vec::new();
         let mut seen_rows = HashSet::new();
+        let mut violations = Vec::new();
+        let mut invalid_rows = 0;
+        let mut duplicate_rows = 0;
+        let mut rejected_rows = Vec::new();
 
-        for (i, line) in lines.iter().enumerate().skip(data_start) {
+        for (i, line) in lines[..data_end].iter().enumerate().skip(data_start) {
             if line.trim().is_empty() {
                 continue;
             }
@@ -61,20 +197,78 @@ impl Transformer {
                 continue;
             }
 
+            if let Some(min_columns) = self.config.min_columns {
+                if processed_line.split('\t').count() < min_columns {
+                    debug!("Rejecting short row at line {}", i + 1);
+                    rejected_rows.push(line.to_string());
+                    continue;
+                }
+            }
+
+            let processed_line = self.apply_row_ops(processed_line);
+
             // Check for duplicates if deduplication is enabled
             if self.config.dedupe_rows {
-                if seen_rows.contains(processed_line) {
+                if seen_rows.contains(processed_line.as_ref()) {
                     debug!("Skipping duplicate row at line {}", i + 1);
+                    duplicate_rows += 1;
                     continue;
                 }
                 seen_rows.insert(processed_line.to_string());
             }
 
+            let row_violations = self.validate_row(&processed_line);
+            if !row_violations.is_empty() {
+                invalid_rows += 1;
+                for violation in row_violations {
+                    violations.push(format!("line {}: {}", i + 1, violation));
+                }
+                if self.config.validation.on_error == "drop" {
+                    debug!("Dropping invalid row at line {}", i + 1);
+                    continue;
+                }
+            }
+
             data_rows.push(processed_line.to_string());
         }
 
         debug!("Extracted {} data rows", data_rows.len());
 
+        if !rejected_rows.is_empty() {
+            warn!(
+                "Rejected {} rows with fewer than {} columns",
+                rejected_rows.len(),
+                self.config.min_columns.unwrap_or_default()
+            );
+        }
+
+        if !violations.is_empty() {
+            for violation in violations.iter().take(10) {
+                warn!("Validation violation at {}", violation);
+            }
+            if violations.len() > 10 {
+                warn!("... and {} more violations", violations.len() - 10);
+            }
+
+            match self.config.validation.on_error.as_str() {
+                "fail" => anyhow::bail!(
+                    "Validation failed: {} violations in {} rows",
+                    violations.len(),
+                    invalid_rows
+                ),
+                "drop" => warn!(
+                    "Validation dropped {} rows ({} violations)",
+                    invalid_rows,
+                    violations.len()
+                ),
+                _ => warn!(
+                    "Validation found {} violations in {} rows (rows kept)",
+                    violations.len(),
+                    invalid_rows
+                ),
+            }
+        }
+
         // Create output file
         let mut temp_file = NamedTempFile::new()?;
 
@@ -95,6 +289,7 @@ impl Transformer {
         temp_file.write_all(line_ending.as_bytes())?;
 
         // Write data rows
+        let row_count = data_rows.len();
         for row in data_rows {
             let processed_row = if self.config.format == "csv" {
                 // Convert tabs to commas for CSV
@@ -109,17 +304,232 @@ impl Transformer {
         temp_file.flush()?;
         info!("Transformed file created: {}", temp_file.path().display());
 
-        Ok(temp_file)
+        let report = TransformReport {
+            source_file: input_path.display().to_string(),
+            generated_at: Utc::now(),
+            input_lines: lines.len(),
+            data_rows: row_count,
+            duplicate_rows,
+            invalid_rows,
+            rejected_rows: rejected_rows.len(),
+            incomplete_last_line_dropped,
+            encoding: encoding.to_string(),
+            sha256: content_hash(temp_file.path()).await?,
+        };
+
+        Ok(TransformOutput {
+            file: temp_file,
+            data_rows: row_count,
+            rejected_rows,
+            report,
+        })
+    }
+
+    pub async fn write_report(&self, report: &TransformReport, path: &Path) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(report).context("Failed to serialize transform report")?;
+        tokio::fs::write(path, json)
+            .await
+            .with_context(|| format!("Failed to write transform report: {}", path.display()))?;
+        info!("Wrote transform report to {}", path.display());
+        Ok(())
     }
 
-    async fn read_file_content(&self, path: &Path) -> Result<String> {
-        let bytes = tokio::fs::read(path)
+    /// Checks `assert_header` and `assert_columns` against the transformed output,
+    /// to catch a changed report format before it is uploaded.
+    pub async fn check_output(&self, output: &TransformOutput) -> Result<()> {
+        if self.config.assert_header.is_none() && self.config.assert_columns.is_none() {
+            return Ok(());
+        }
+
+        let path = output.file.path();
+        let content = tokio::fs::read_to_string(path)
             .await
-            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+            .with_context(|| format!("Failed to read transformed file: {}", path.display()))?;
+        let mut lines = content.lines();
+        let header = lines.next().unwrap_or_default();
+
+        if let Some(expected) = &self.config.assert_header {
+            if header != expected {
+                anyhow::bail!(
+                    "Transformed header {:?} does not match transform.assert_header {:?}; \
+                     the report format may have changed",
+                    header,
+                    expected
+                );
+            }
+        }
+
+        if let Some(expected) = self.config.assert_columns {
+            let delimiter = if self.config.format == "csv" {
+                ','
+            } else {
+                '\t'
+            };
+            match lines.next() {
+                Some(row) => {
+                    let columns = row.split(delimiter).count();
+                    if columns != expected {
+                        anyhow::bail!(
+                            "First data row has {} columns, transform.assert_columns expects {}; \
+                             the report format may have changed",
+                            columns,
+                            expected
+                        );
+                    }
+                }
+                None => debug!("No data rows to check against transform.assert_columns"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes rejected rows as-is, one per line, replacing any earlier reject file.
+    pub async fn write_rejects(&self, rows: &[String], path: &Path) -> Result<()> {
+        let mut content = rows.join("\n");
+        content.push('\n');
+        tokio::fs::write(path, content)
+            .await
+            .with_context(|| format!("Failed to write reject file: {}", path.display()))?;
+        info!("Wrote {} rejected rows to {}", rows.len(), path.display());
+        Ok(())
+    }
+
+    /// Concatenates files in the given order into one temp file, keeping the header
+    /// block of the first file only.
+    pub async fn merge_files(&self, input_paths: &[std::path::PathBuf]) -> Result<NamedTempFile> {
+        let mut merged = NamedTempFile::new().context("Failed to create merged temp file")?;
+
+        for (index, input_path) in input_paths.iter().enumerate() {
+            let (content, _) = self.read_file_content(input_path).await?;
+            let lines: Vec<&str> = content.lines().collect();
+            let start = if index == 0 {
+                0
+            } else {
+                self.find_data_start(&lines)?
+            };
+            debug!(
+                "Merging {} (skipping {} header lines)",
+                input_path.display(),
+                start
+            );
+
+            for line in lines.iter().skip(start) {
+                merged.write_all(line.as_bytes())?;
+                merged.write_all(b"\n")?;
+            }
+        }
+
+        merged.flush()?;
+        info!(
+            "Merged {} files into {}",
+            input_paths.len(),
+            merged.path().display()
+        );
+        Ok(merged)
+    }
+
+    /// Counts data rows in an untransformed file using the same header detection.
+    pub async fn count_data_rows(&self, input_path: &Path) -> Result<usize> {
+        let (content, _) = self.read_file_content(input_path).await?;
+        let lines: Vec<&str> = content.lines().collect();
+        let data_start = self.find_data_start(&lines)?;
+
+        Ok(lines
+            .iter()
+            .skip(data_start)
+            .filter(|line| !line.trim().is_empty())
+            .count())
+    }
+
+    /// Applies `row_ops` in order; rows are only copied when there are ops to apply.
+    fn apply_row_ops<'a>(&self, row: &'a str) -> Cow<'a, str> {
+        if self.row_ops.is_empty() {
+            return Cow::Borrowed(row);
+        }
+
+        let mut cells: Vec<String> = row.split('\t').map(str::to_string).collect();
+        for op in &self.row_ops {
+            op.apply(&mut cells);
+        }
+        Cow::Owned(cells.join("\t"))
+    }
+
+    fn validate_row(&self, row: &str) -> Vec<String> {
+        let cells: Vec<&str> = row.split('\t').collect();
+        let mut violations = Vec::new();
+
+        for compiled in &self.rules {
+            let rule = &compiled.rule;
+            let value = match cells.get(rule.column) {
+                Some(cell) => cell.trim(),
+                None => {
+                    violations.push(format!("column {} is missing", rule.column));
+                    continue;
+                }
+            };
+
+            let len = value.chars().count();
+            if let Some(min) = rule.min_length {
+                if len < min {
+                    violations.push(format!(
+                        "column {} '{}' shorter than {} characters",
+                        rule.column, value, min
+                    ));
+                }
+            }
+            if let Some(max) = rule.max_length {
+                if len > max {
+                    violations.push(format!(
+                        "column {} '{}' longer than {} characters",
+                        rule.column, value, max
+                    ));
+                }
+            }
+            if let Some(regex) = &compiled.regex {
+                if !regex.is_match(value) {
+                    violations.push(format!(
+                        "column {} '{}' does not match {}",
+                        rule.column,
+                        value,
+                        regex.as_str()
+                    ));
+                }
+            }
+        }
+
+        violations
+    }
+
+    /// Returns the decoded content and the name of the encoding it was read as.
+    async fn read_file_content(&self, path: &Path) -> Result<(String, &'static str)> {
+        let (content, encoding) = self.decode_file_content(path).await?;
+
+        // lines() doesn't split on a lone CR, so normalize CR and CRLF to LF
+        if content.contains('\r') {
+            debug!("Normalizing CR/CRLF line endings to LF");
+            return Ok((content.replace("\r\n", "\n").replace('\r', "\n"), encoding));
+        }
+        Ok((content, encoding))
+    }
+
+    async fn decode_file_content(&self, path: &Path) -> Result<(String, &'static str)> {
+        let bytes = read_input_bytes(path).await?;
 
         // Try UTF-8 first
-        if let Ok(content) = String::from_utf8(bytes.clone()) {
-            return Ok(content);
+        let invalid = match std::str::from_utf8(&bytes) {
+            Ok(content) => return Ok((content.to_string(), "utf-8")),
+            Err(e) => e,
+        };
+
+        // Some consumers treat non-UTF-8 input as a corrupt extraction
+        if self.config.encoding_strict {
+            anyhow::bail!(
+                "File is not valid UTF-8 (invalid byte at offset {}) and transform.encoding_strict is set: {}",
+                invalid.valid_up_to(),
+                path.display()
+            );
         }
 
         // Fallback to Windows-1252
@@ -130,7 +540,7 @@ impl Transformer {
             warn!("Windows-1252 conversion had errors, proceeding with best-effort result");
         }
 
-        Ok(content.to_string())
+        Ok((content.to_string(), "windows-1252"))
     }
 
     fn find_data_start(&self, lines: &[&str]) -> Result<usize> {
@@ -166,6 +576,7 @@ impl Transformer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::ValidationConfig;
 
     use std::io::Write;
 
@@ -178,6 +589,27 @@ mod tests {
             dedupe_rows: false,
             trim_whitespace: true,
             output_line_ending: "lf".to_string(),
+            validation: ValidationConfig::default(),
+            upload_original_too: false,
+            original_endpoint: String::new(),
+            min_columns: None,
+            drop_incomplete_last_line: false,
+            write_report: false,
+            upload_report: false,
+            assert_header: None,
+            assert_columns: None,
+            row_ops: Vec::new(),
+            skip_upload_if_empty: false,
+            encoding_strict: false,
+        }
+    }
+
+    fn delivery_rule() -> ColumnRule {
+        ColumnRule {
+            column: 1,
+            regex: Some(r"^\d{10}$".to_string()),
+            min_length: None,
+            max_length: None,
         }
     }
 
@@ -188,6 +620,69 @@ mod tests {
         Ok(file)
     }
 
+    #[test]
+    fn test_row_op_parse() {
+        assert_eq!(RowOp::parse("uppercase:0").unwrap(), RowOp::Uppercase(0));
+        assert_eq!(RowOp::parse("trim:1").unwrap(), RowOp::Trim(1));
+        assert_eq!(RowOp::parse("zeropad:2:10").unwrap(), RowOp::ZeroPad(2, 10));
+        assert!(RowOp::parse("zeropad:2").is_err());
+        assert!(RowOp::parse("uppercase:x").is_err());
+        assert!(RowOp::parse("reverse:0").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transform_row_ops() {
+        let mut config = create_test_config();
+        config.header_rows_to_skip = 0;
+        config.row_ops = vec![
+            "uppercase:0".to_string(),
+            "trim:1".to_string(),
+            "zeropad:2:10".to_string(),
+            "zeropad:5:4".to_string(),
+        ];
+        let transformer = Transformer::new(&config).unwrap();
+
+        let input = create_test_file(
+            "Plant\tDelivery\tMaterial\nplt01\t 9876543210 \t55512345\nPLT02\t9876543211\t1\n",
+        )
+        .unwrap();
+        let output = transformer.transform_file(input.path()).await.unwrap();
+
+        let output_content = std::fs::read_to_string(output.file.path()).unwrap();
+        assert_eq!(
+            output_content,
+            "Plant\tDelivery\tMaterial\nPLT01\t9876543210\t0055512345\nPLT02\t9876543211\t0000000001\n"
+        );
+
+        config.row_ops = vec!["zeropad:1".to_string()];
+        assert!(Transformer::new(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_output_assertions() {
+        let mut config = create_test_config();
+        config.header_rows_to_skip = 0;
+        let input =
+            create_test_file("Plant\tDelivery\tMaterial\nPLT01\t9876543210\t55512345\n").unwrap();
+
+        config.assert_header = Some("Plant\tDelivery\tMaterial".to_string());
+        config.assert_columns = Some(3);
+        let transformer = Transformer::new(&config).unwrap();
+        let output = transformer.transform_file(input.path()).await.unwrap();
+        transformer.check_output(&output).await.unwrap();
+
+        config.assert_header = Some("Plant\tDelivery\tMaterial\tQty".to_string());
+        let transformer = Transformer::new(&config).unwrap();
+        let err = transformer.check_output(&output).await.unwrap_err();
+        assert!(err.to_string().contains("transform.assert_header"));
+
+        config.assert_header = None;
+        config.assert_columns = Some(4);
+        let transformer = Transformer::new(&config).unwrap();
+        let err = transformer.check_output(&output).await.unwrap_err();
+        assert!(err.to_string().contains("has 3 columns"));
+    }
+
     #[tokio::test]
     async fn test_transform_basic() {
         let config = create_test_config();
@@ -205,15 +700,136 @@ Run Time   :                           14:30:22
         PLT02	9876543211	55512346"#;
 
         let input_file = create_test_file(test_content).unwrap();
-        let output_file = transformer.transform_file(input_file.path()).await.unwrap();
+        let output = transformer.transform_file(input_file.path()).await.unwrap();
 
-        let output_content = std::fs::read_to_string(output_file.path()).unwrap();
+        let output_content = std::fs::read_to_string(output.file.path()).unwrap();
         let expected =
             "Plant\tDelivery\tMaterial\nPLT01\t9876543210\t55512345\nPLT02\t9876543211\t55512346\n";
 
         assert_eq!(output_content, expected);
     }
 
+    #[tokio::test]
+    async fn test_merge_files_skips_repeated_headers() {
+        let config = create_test_config();
+        let transformer = Transformer::new(&config).unwrap();
+
+        let header = "Report\nCorp\n\nUser X\nRun Date 2025-01-15\nRun Time 14:30:22\n\nPlant\tDelivery\tMaterial\n";
+        let first = create_test_file(&format!("{}PLT01\t9876543210\t55512345\n", header)).unwrap();
+        let second = create_test_file(&format!("{}PLT02\t9876543211\t55512346\n", header)).unwrap();
+
+        let merged = transformer
+            .merge_files(&[first.path().to_path_buf(), second.path().to_path_buf()])
+            .await
+            .unwrap();
+        let output = transformer.transform_file(merged.path()).await.unwrap();
+
+        let output_content = std::fs::read_to_string(output.file.path()).unwrap();
+        assert_eq!(
+            output_content,
+            "Plant\tDelivery\tMaterial\nPLT01\t9876543210\t55512345\nPLT02\t9876543211\t55512346\n"
+        );
+        assert_eq!(output.data_rows, 2);
+    }
+
+    #[tokio::test]
+    async fn test_transform_rejects_short_rows() {
+        let mut config = create_test_config();
+        config.min_columns = Some(3);
+        let transformer = Transformer::new(&config).unwrap();
+
+        let header = "Report\nCorp\n\nUser X\nRun Date 2025-01-15\nRun Time 14:30:22\n\nPlant\tDelivery\tMaterial\n";
+        let input_file = create_test_file(&format!(
+            "{}PLT01\t9876543210\t55512345\n  PLT02\t9876543211\nPLT03\t9876543212\t55512347\n",
+            header
+        ))
+        .unwrap();
+        let output = transformer.transform_file(input_file.path()).await.unwrap();
+
+        let output_content = std::fs::read_to_string(output.file.path()).unwrap();
+        assert_eq!(
+            output_content,
+            "Plant\tDelivery\tMaterial\nPLT01\t9876543210\t55512345\nPLT03\t9876543212\t55512347\n"
+        );
+        assert_eq!(output.data_rows, 2);
+        assert_eq!(
+            output.rejected_rows,
+            vec!["  PLT02\t9876543211".to_string()]
+        );
+
+        let rejects_path = input_file.path().with_extension("rejects");
+        transformer
+            .write_rejects(&output.rejected_rows, &rejects_path)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&rejects_path).unwrap(),
+            "  PLT02\t9876543211\n"
+        );
+        std::fs::remove_file(&rejects_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transform_drops_incomplete_last_line() {
+        let mut config = create_test_config();
+        config.drop_incomplete_last_line = true;
+        let transformer = Transformer::new(&config).unwrap();
+
+        let header = "Report\nCorp\n\nUser X\nRun Date 2025-01-15\nRun Time 14:30:22\n\nPlant\tDelivery\tMaterial\n";
+        let partial = create_test_file(&format!(
+            "{}PLT01\t9876543210\t55512345\nPLT02\t98765",
+            header
+        ))
+        .unwrap();
+        let output = transformer.transform_file(partial.path()).await.unwrap();
+        assert_eq!(output.data_rows, 1);
+
+        let complete =
+            create_test_file(&format!("{}PLT01\t9876543210\t55512345\r\n", header)).unwrap();
+        let output = transformer.transform_file(complete.path()).await.unwrap();
+        assert_eq!(output.data_rows, 1);
+    }
+
+    #[tokio::test]
+    async fn test_transform_cr_only_line_endings() {
+        let config = create_test_config();
+        let transformer = Transformer::new(&config).unwrap();
+
+        let test_content = "Report\rCorp\r\rUser X\rRun Date 2025-01-15\rRun Time 14:30:22\r\rPlant\tDelivery\tMaterial\rPLT01\t9876543210\t55512345\r\nPLT02\t9876543211\t55512346\r";
+
+        let input_file = create_test_file(test_content).unwrap();
+        let output = transformer.transform_file(input_file.path()).await.unwrap();
+
+        let output_content = std::fs::read_to_string(output.file.path()).unwrap();
+        assert_eq!(
+            output_content,
+            "Plant\tDelivery\tMaterial\nPLT01\t9876543210\t55512345\nPLT02\t9876543211\t55512346\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encoding_strict_rejects_windows_1252() {
+        let mut config = create_test_config();
+        config.header_rows_to_skip = 0;
+        let mut input_file = NamedTempFile::new().unwrap();
+        input_file
+            .write_all(b"Plant\tDelivery\tMaterial\nPLT01\t9876543210\tStra\xdfe\n")
+            .unwrap();
+
+        let transformer = Transformer::new(&config).unwrap();
+        let output = transformer.transform_file(input_file.path()).await.unwrap();
+        let output_content = std::fs::read_to_string(output.file.path()).unwrap();
+        assert!(output_content.contains("Stra\u{df}e"));
+
+        config.encoding_strict = true;
+        let transformer = Transformer::new(&config).unwrap();
+        let err = match transformer.transform_file(input_file.path()).await {
+            Ok(_) => panic!("non-UTF-8 input should fail in strict mode"),
+            Err(e) => e,
+        };
+        assert!(err.to_string().contains("offset 45"));
+    }
+
     #[tokio::test]
     async fn test_transform_csv() {
         let mut config = create_test_config();
@@ -231,9 +847,9 @@ Run Time   :                           14:30:22
         PLT01	9876543210	55512345"#;
 
         let input_file = create_test_file(test_content).unwrap();
-        let output_file = transformer.transform_file(input_file.path()).await.unwrap();
+        let output = transformer.transform_file(input_file.path()).await.unwrap();
 
-        let output_content = std::fs::read_to_string(output_file.path()).unwrap();
+        let output_content = std::fs::read_to_string(output.file.path()).unwrap();
         let expected = "Plant,Delivery,Material\nPLT01,9876543210,55512345\n";
 
         assert_eq!(output_content, expected);
@@ -258,12 +874,105 @@ Run Time   :                           14:30:22
         PLT02	9876543211	55512346"#;
 
         let input_file = create_test_file(test_content).unwrap();
-        let output_file = transformer.transform_file(input_file.path()).await.unwrap();
+        let output = transformer.transform_file(input_file.path()).await.unwrap();
 
-        let output_content = std::fs::read_to_string(output_file.path()).unwrap();
+        let output_content = std::fs::read_to_string(output.file.path()).unwrap();
         let expected =
             "Plant\tDelivery\tMaterial\nPLT01\t9876543210\t55512345\nPLT02\t9876543211\t55512346\n";
 
         assert_eq!(output_content, expected);
     }
+
+    #[tokio::test]
+    async fn test_transform_report() {
+        let mut config = create_test_config();
+        config.dedupe_rows = true;
+        let transformer = Transformer::new(&config).unwrap();
+
+        let test_content = r#"In-Transfer (Push Delivery) Materials Report
+Acme Manufacturing Corp
+
+User                                   TESTUSER
+Run Date   :                           2025-01-15
+Run Time   :                           14:30:22
+
+        Plant	Delivery	Material
+        PLT01	9876543210	55512345
+        PLT01	9876543210	55512345
+        PLT02	9876543211	55512346"#;
+
+        let input_file = create_test_file(test_content).unwrap();
+        let output = transformer.transform_file(input_file.path()).await.unwrap();
+
+        let report = &output.report;
+        assert_eq!(report.data_rows, 2);
+        assert_eq!(report.duplicate_rows, 1);
+        assert_eq!(report.encoding, "utf-8");
+        assert_eq!(
+            report.sha256,
+            crate::state::content_hash(output.file.path())
+                .await
+                .unwrap()
+        );
+
+        let report_path = input_file.path().with_extension("report.json");
+        transformer
+            .write_report(report, &report_path)
+            .await
+            .unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(written["duplicate_rows"], 1);
+        std::fs::remove_file(&report_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transform_validation_drop() {
+        let mut config = create_test_config();
+        config.validation.rules = vec![delivery_rule()];
+        config.validation.on_error = "drop".to_string();
+        let transformer = Transformer::new(&config).unwrap();
+
+        let test_content = r#"In-Transfer (Push Delivery) Materials Report
+Acme Manufacturing Corp
+
+User                                   TESTUSER
+Run Date   :                           2025-01-15
+Run Time   :                           14:30:22
+
+        Plant	Delivery	Material
+        PLT01	98765	55512345
+        PLT02	9876543211	55512346"#;
+
+        let input_file = create_test_file(test_content).unwrap();
+        let output = transformer.transform_file(input_file.path()).await.unwrap();
+
+        let output_content = std::fs::read_to_string(output.file.path()).unwrap();
+        let expected = "Plant\tDelivery\tMaterial\nPLT02\t9876543211\t55512346\n";
+
+        assert_eq!(output_content, expected);
+    }
+
+    #[tokio::test]
+    async fn test_transform_validation_fail() {
+        let mut config = create_test_config();
+        config.validation.rules = vec![delivery_rule()];
+        config.validation.on_error = "fail".to_string();
+        let transformer = Transformer::new(&config).unwrap();
+
+        let test_content = r#"In-Transfer (Push Delivery) Materials Report
+Acme Manufacturing Corp
+
+User                                   TESTUSER
+Run Date   :                           2025-01-15
+Run Time   :                           14:30:22
+
+        Plant	Delivery	Material
+        PLT01	98765	55512345"#;
+
+        let input_file = create_test_file(test_content).unwrap();
+        let result = transformer.transform_file(input_file.path()).await;
+
+        assert!(result.is_err());
+    }
 }
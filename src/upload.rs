@@ -2,27 +2,195 @@ use anyhow::{Context, Result};
 use base64::{engine::general_purpose, Engine as _};
 use log::{debug, error, info, warn};
 use reqwest::{Client, StatusCode};
-use serde_json::json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::SeekFrom;
 use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::RwLock;
+use std::time::SystemTime;
+use thiserror::Error;
 use tokio::fs;
-use tokio::time::{sleep, Duration};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::time::{sleep, timeout_at, Duration, Instant};
 
 use crate::config::{ApiConfig, RetryConfig};
 
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("Rate limited ({status}): {body}")]
+    RateLimited {
+        status: StatusCode,
+        retry_after_secs: Option<u64>,
+        body: String,
+    },
+    #[error("Client error ({status}): {body}")]
+    Client { status: StatusCode, body: String },
+    #[error("Server error ({status}): {body}")]
+    Server { status: StatusCode, body: String },
+    #[error(
+        "Upload redirected ({status}) to {}; set api.follow_redirects to resend",
+        .location.as_deref().unwrap_or("<no location>")
+    )]
+    Redirect {
+        status: StatusCode,
+        location: Option<String>,
+    },
+    #[error("Upload deadline exceeded after {attempts} attempt(s) ({budget_secs}s total budget)")]
+    DeadlineExceeded { budget_secs: u64, attempts: u32 },
+    #[error(
+        "Request body is {size} bytes, over api.max_body_bytes ({limit}); \
+         use multipart mode or split the file"
+    )]
+    BodyTooLarge { size: u64, limit: u64 },
+    #[error("Precondition failed (412), the resource changed on the server: {body}")]
+    PreconditionFailed { body: String },
+    #[error("Upload conflict (409), a partial upload may be blocking it: {body}")]
+    Conflict { body: String },
+    /// The server's `Range` after a resumable chunk did not move forward within the file
+    #[error(
+        "Resumable upload stalled: after the chunk at byte {offset}, the server acknowledged \
+         up to byte {acknowledged} of {total}"
+    )]
+    InvalidRange {
+        offset: u64,
+        acknowledged: u64,
+        total: u64,
+    },
+    /// A 2xx whose body does not match `api.response_schema`, such as an HTML error page
+    #[error("Response does not match api.response_schema ({reason}): {body}")]
+    InvalidResponse { reason: String, body: String },
+    #[error(
+        "Upload skipped: {failures} consecutive uploads failed this cycle and the endpoint \
+         appears down (retry.circuit_break_after)"
+    )]
+    CircuitOpen { failures: u32 },
+    /// The request never got a response; the TLS, DNS, or socket cause is in `source`.
+    /// Timeouts and refused connections are retried in every mode. Any other
+    /// failure, such as a connection dropped while the request or body was being
+    /// sent, is only retried when the upload is idempotent, since a POST may
+    /// already have reached the server. A resumable retry resumes at the last
+    /// acknowledged byte.
+    #[error("Failed to send {what}")]
+    Transport {
+        what: &'static str,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("Unexpected status code: {status} - {body}")]
+    Unexpected { status: StatusCode, body: String },
+}
+
+/// Redirect hops followed for a single upload attempt before giving up
+const MAX_REDIRECTS: usize = 5;
+
+/// What an upload sends. Resumable uploads of a file read each chunk from
+/// disk; everything else is sent from memory.
+enum UploadSource<'a> {
+    File(&'a Path),
+    /// `size` and `modified` describe the source file, which may differ from the
+    /// content when it is transform output
+    Bytes {
+        content: Vec<u8>,
+        size: u64,
+        modified: SystemTime,
+    },
+}
+
+/// One upload attempt: the id sent in `api.correlation_header`, what it sent and how long it took
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadAttempt {
+    pub correlation_id: String,
+    pub bytes: u64,
+    pub duration_ms: u64,
+    pub success: bool,
+}
+
+impl UploadAttempt {
+    fn megabytes_per_sec(&self) -> f64 {
+        let secs = (self.duration_ms.max(1)) as f64 / 1000.0;
+        self.bytes as f64 / 1_000_000.0 / secs
+    }
+}
+
+/// Every attempt of one upload, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct UploadAttempts(pub Vec<UploadAttempt>);
+
+impl UploadAttempts {
+    pub fn correlation_ids(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|attempt| attempt.correlation_id.as_str())
+    }
+}
+
+impl std::fmt::Display for UploadAttempts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ids: Vec<&str> = self.correlation_ids().collect();
+        write!(f, "correlation ids: {}", ids.join(", "))
+    }
+}
+
+impl UploadError {
+    /// `idempotent` is whether resending the request is harmless, see `Uploader::is_idempotent`
+    fn is_retryable(&self, idempotent: bool) -> bool {
+        match self {
+            UploadError::RateLimited { .. } | UploadError::Server { .. } => true,
+            UploadError::Transport { source, .. } => {
+                idempotent || source.is_timeout() || source.is_connect()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The error and each of its causes on its own line. reqwest and hyper often
+/// repeat a cause in their own message, so a cause already shown is skipped.
+pub fn error_chain(error: &anyhow::Error) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for cause in error.chain() {
+        let text = cause.to_string();
+        if lines
+            .last()
+            .is_some_and(|previous| previous.contains(&text))
+        {
+            continue;
+        }
+        lines.push(text);
+    }
+    lines.join("\n    caused by: ")
+}
+
 pub struct Uploader {
     client: Client,
     api_config: ApiConfig,
     retry_config: RetryConfig,
+    json_template: Option<Value>,
+    response_schema: Option<jsonschema::Validator>,
+    /// Static token, or the latest output of `bearer_token_command`
+    bearer_token: RwLock<String>,
+    /// Failed uploads in a row this cycle, for `retry.circuit_break_after`
+    consecutive_failures: AtomicU32,
 }
 
 impl Uploader {
     pub fn new(api_config: &ApiConfig, retry_config: &RetryConfig) -> Result<Self> {
-        let client_builder = Client::builder().timeout(Duration::from_secs(30));
+        // Redirects are handled in try_upload so the body is rebuilt and resent;
+        // reqwest's own policy would silently drop a streamed multipart body.
+        let client_builder = Client::builder()
+            .timeout(Duration::from_secs(api_config.attempt_timeout_secs))
+            .pool_idle_timeout(Duration::from_secs(api_config.pool_idle_timeout_secs))
+            .pool_max_idle_per_host(api_config.pool_max_idle_per_host)
+            .redirect(reqwest::redirect::Policy::none());
+        let client_builder = apply_protocol(
+            client_builder,
+            &api_config.http_version,
+            api_config.min_tls_version.as_deref(),
+        );
 
         // Configure authentication
         match api_config.auth.as_str() {
             "bearer" => {
-                if api_config.bearer_token.is_empty() {
+                if api_config.bearer_token.is_empty() && api_config.bearer_token_command.is_none() {
                     anyhow::bail!("Bearer token is required when auth is 'bearer'");
                 }
                 // Bearer token will be added in the request
@@ -45,141 +213,1040 @@ impl Uploader {
             .build()
             .context("Failed to create HTTP client")?;
 
+        if !api_config.correlation_header.is_empty() {
+            reqwest::header::HeaderName::from_bytes(api_config.correlation_header.as_bytes())
+                .with_context(|| {
+                    format!(
+                        "Invalid api.correlation_header: {}",
+                        api_config.correlation_header
+                    )
+                })?;
+        }
+
+        if let Some(mime) = &api_config.file_mime {
+            reqwest::multipart::Part::bytes(Vec::new())
+                .mime_str(mime)
+                .with_context(|| format!("Invalid api.file_mime: {}", mime))?;
+        }
+
+        let json_template = api_config
+            .json_template
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()
+            .context("api.json_template is not valid JSON")?;
+        let response_schema = api_config
+            .response_schema
+            .as_deref()
+            .map(load_response_schema)
+            .transpose()?;
+
+        let bearer_token = match &api_config.bearer_token_command {
+            Some(command) if api_config.auth == "bearer" => {
+                info!("Fetching bearer token from api.bearer_token_command");
+                let (program, args) = token_command(command);
+                let output = std::process::Command::new(program)
+                    .args(args)
+                    .output()
+                    .with_context(|| format!("Failed to run bearer token command: {}", command))?;
+                token_from_output(command, output)?
+            }
+            _ => api_config.bearer_token.clone(),
+        };
+
         Ok(Self {
             client,
             api_config: api_config.clone(),
             retry_config: retry_config.clone(),
+            json_template,
+            response_schema,
+            bearer_token: RwLock::new(bearer_token),
+            consecutive_failures: AtomicU32::new(0),
+        })
+    }
+
+    /// Starts a new cycle with the circuit breaker closed.
+    pub fn reset_circuit(&self) {
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+    }
+
+    /// Fails once `retry.circuit_break_after` uploads in a row have failed this cycle.
+    pub fn check_circuit(&self) -> Result<()> {
+        let failures = self.consecutive_failures.load(Ordering::SeqCst);
+        match self.retry_config.circuit_break_after {
+            Some(limit) if failures >= limit => Err(UploadError::CircuitOpen { failures }.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Re-runs `bearer_token_command` for short-lived tokens when per-run refresh is on.
+    pub async fn refresh_bearer_token(&self) -> Result<()> {
+        let command = match &self.api_config.bearer_token_command {
+            Some(command)
+                if self.api_config.auth == "bearer" && self.api_config.bearer_token_refresh =>
+            {
+                command
+            }
+            _ => return Ok(()),
+        };
+
+        debug!("Refreshing bearer token");
+        let (program, args) = token_command(command);
+        let output = tokio::process::Command::new(program)
+            .args(args)
+            .output()
+            .await
+            .with_context(|| format!("Failed to run bearer token command: {}", command))?;
+        let token = token_from_output(command, output)?;
+        *self.bearer_token.write().unwrap() = token;
+        Ok(())
+    }
+
+    /// `form_fields` are sent after `api.extra_fields`, whose `{name}` placeholders
+    /// are filled from `captures` (the named groups of the file name). Multipart
+    /// sends them all as form fields; json_base64 adds them to the payload.
+    pub async fn upload_file(
+        &self,
+        file_path: &Path,
+        original_filename: &str,
+        form_fields: &[(String, String)],
+        captures: &[(String, String)],
+    ) -> Result<UploadAttempts> {
+        self.upload_derived_file(
+            file_path,
+            file_path,
+            original_filename,
+            form_fields,
+            captures,
+        )
+        .await
+    }
+
+    /// Like `upload_file`, for a file produced from `source_path` such as the
+    /// transform output. The size and modification time sent with
+    /// `api.include_file_metadata` are the source file's.
+    pub async fn upload_derived_file(
+        &self,
+        file_path: &Path,
+        source_path: &Path,
+        original_filename: &str,
+        form_fields: &[(String, String)],
+        captures: &[(String, String)],
+    ) -> Result<UploadAttempts> {
+        let source = self.file_source(file_path, source_path).await?;
+        let fields = self.upload_fields(captures, form_fields);
+        let filename = self.target_filename(original_filename, captures);
+        self.upload_source(&source, &filename, &fields).await
+    }
+
+    /// `api.override_filename` with its placeholders filled, or `original_filename`
+    /// when it is unset.
+    fn target_filename(&self, original_filename: &str, captures: &[(String, String)]) -> String {
+        match &self.api_config.override_filename {
+            Some(template) => expand_captures(template, captures)
+                .replace("{date}", &chrono::Local::now().format("%Y%m%d").to_string()),
+            None => original_filename.to_string(),
+        }
+    }
+
+    /// `api.extra_fields` with their `{name}` placeholders filled, then `form_fields`.
+    /// A placeholder without a matching capture is sent as written.
+    fn upload_fields(
+        &self,
+        captures: &[(String, String)],
+        form_fields: &[(String, String)],
+    ) -> Vec<(String, String)> {
+        let mut fields: Vec<(String, String)> = self
+            .api_config
+            .extra_fields
+            .iter()
+            .map(|(key, value)| (key.clone(), expand_captures(value, captures)))
+            .collect();
+        fields.extend(form_fields.iter().cloned());
+        fields
+    }
+
+    /// Uploads in-memory content as if it were a file named `filename`, with the
+    /// same retries and failover as `upload_file`. The runner itself always
+    /// uploads files; this is for generated content such as test payloads.
+    #[allow(dead_code)]
+    pub async fn upload_bytes(&self, content: Vec<u8>, filename: &str) -> Result<UploadAttempts> {
+        let source = UploadSource::Bytes {
+            size: content.len() as u64,
+            content,
+            modified: SystemTime::now(),
+        };
+        let fields = self.upload_fields(&[], &[]);
+        let filename = self.target_filename(filename, &[]);
+        self.upload_source(&source, &filename, &fields).await
+    }
+
+    async fn file_source<'a>(
+        &self,
+        file_path: &'a Path,
+        source_path: &Path,
+    ) -> Result<UploadSource<'a>> {
+        if self.api_config.mode == "resumable" {
+            return Ok(UploadSource::File(file_path));
+        }
+
+        debug!("Reading file for upload: {}", file_path.display());
+        let content = fs::read(file_path)
+            .await
+            .with_context(|| format!("Failed to read file for upload: {}", file_path.display()))?;
+        let metadata = fs::metadata(source_path)
+            .await
+            .with_context(|| format!("Failed to read file metadata: {}", source_path.display()))?;
+        let modified = metadata
+            .modified()
+            .context("Failed to read file modification time")?;
+        Ok(UploadSource::Bytes {
+            content,
+            size: metadata.len(),
+            modified,
         })
     }
 
-    pub async fn upload_file(&self, file_path: &Path, original_filename: &str) -> Result<()> {
+    async fn upload_source(
+        &self,
+        source: &UploadSource<'_>,
+        original_filename: &str,
+        form_fields: &[(String, String)],
+    ) -> Result<UploadAttempts> {
+        if self.api_config.mode == "file" {
+            return self.write_to_sink(source, original_filename);
+        }
+
+        self.check_circuit()?;
+
+        let result = self
+            .upload_with_failover(source, original_filename, form_fields)
+            .await;
+        if result.is_ok() {
+            self.reset_circuit();
+            return result;
+        }
+
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.retry_config.circuit_break_after == Some(failures) {
+            error!(
+                "{} uploads in a row failed; the endpoint appears down, skipping the remaining uploads this cycle",
+                failures
+            );
+        }
+        result
+    }
+
+    async fn upload_with_failover(
+        &self,
+        source: &UploadSource<'_>,
+        original_filename: &str,
+        form_fields: &[(String, String)],
+    ) -> Result<UploadAttempts> {
+        let upload_name = self.upload_name(original_filename);
+        let original_filename = upload_name.as_str();
+        let deadline = self.deadline();
+
+        let primary_result = self
+            .upload_with_retries(
+                &self.api_config.endpoint,
+                source,
+                original_filename,
+                form_fields,
+                deadline,
+            )
+            .await;
+
+        let primary_error = match primary_result {
+            Ok(ids) => return Ok(ids),
+            Err(e) => e,
+        };
+
+        // The total budget covers failover too, a version conflict is not an outage, and
+        // a 2xx with an unexpected body may already have imported the file
+        if let Some(
+            UploadError::DeadlineExceeded { .. }
+            | UploadError::PreconditionFailed { .. }
+            | UploadError::InvalidResponse { .. },
+        ) = primary_error.downcast_ref()
+        {
+            return Err(primary_error);
+        }
+
+        let fallback = match &self.api_config.fallback_endpoint {
+            Some(fallback) => fallback,
+            None => return Err(primary_error),
+        };
+
+        warn!(
+            "Primary endpoint {} failed ({:#}), failing over to fallback endpoint {}",
+            self.api_config.endpoint, primary_error, fallback
+        );
+        let ids = self
+            .upload_with_retries(fallback, source, original_filename, form_fields, deadline)
+            .await
+            .with_context(|| format!("Fallback upload also failed (primary: {})", primary_error))?;
+        info!("File uploaded via fallback endpoint {}", fallback);
+        Ok(ids)
+    }
+
+    /// File mode: writes the content to `api.sink_dir` under the upload name. It goes
+    /// through a temporary file in the same directory, so a sync job never picks up
+    /// a partial copy. An existing file of the same name is replaced.
+    fn write_to_sink(
+        &self,
+        source: &UploadSource<'_>,
+        original_filename: &str,
+    ) -> Result<UploadAttempts> {
+        let content = match source {
+            UploadSource::Bytes { content, .. } => content,
+            UploadSource::File(_) => anyhow::bail!("file uploads must be read into memory first"),
+        };
+        let sink_dir = Path::new(&self.api_config.sink_dir);
+        std::fs::create_dir_all(sink_dir)
+            .with_context(|| format!("Failed to create sink directory: {}", sink_dir.display()))?;
+
+        let target = sink_dir.join(self.upload_name(original_filename));
+        let mut temp = tempfile::NamedTempFile::new_in(sink_dir)
+            .with_context(|| format!("Failed to create file in {}", sink_dir.display()))?;
+        std::io::Write::write_all(&mut temp, content)
+            .with_context(|| format!("Failed to write sink file: {}", target.display()))?;
+        temp.persist(&target)
+            .with_context(|| format!("Failed to replace sink file: {}", target.display()))?;
+
+        info!("File written to sink: {}", target.display());
+        Ok(UploadAttempts::default())
+    }
+
+    /// Uploads to `endpoint` with the usual retries and timeouts, but without failover.
+    pub async fn upload_file_to(
+        &self,
+        endpoint: &str,
+        file_path: &Path,
+        original_filename: &str,
+        captures: &[(String, String)],
+    ) -> Result<UploadAttempts> {
+        let source = self.file_source(file_path, file_path).await?;
+        let upload_name = self.upload_name(&self.target_filename(original_filename, captures));
+        let fields = self.upload_fields(captures, &[]);
+        self.upload_with_retries(endpoint, &source, &upload_name, &fields, self.deadline())
+            .await
+    }
+
+    /// Checks that `endpoint` answers with the upload auth, without sending a file.
+    pub async fn probe(&self, endpoint: &str) -> Result<StatusCode> {
+        let correlation_id = uuid::Uuid::new_v4().to_string();
+        probe_endpoint(|method| {
+            self.prepare_request(self.client.request(method, endpoint), &correlation_id)
+        })
+        .await
+        .with_context(|| format!("Endpoint unreachable: {}", endpoint))
+    }
+
+    fn upload_name(&self, original_filename: &str) -> String {
+        if !self.api_config.sanitize_filename {
+            return original_filename.to_string();
+        }
+
+        let sanitized = self.sanitize_filename(original_filename);
+        if sanitized != original_filename {
+            info!(
+                "Sanitized upload filename: {} -> {}",
+                original_filename, sanitized
+            );
+        }
+        sanitized
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.api_config
+            .total_timeout_secs
+            .map(|secs| Instant::now() + Duration::from_secs(secs))
+    }
+
+    async fn upload_with_retries(
+        &self,
+        endpoint: &str,
+        source: &UploadSource<'_>,
+        original_filename: &str,
+        form_fields: &[(String, String)],
+        deadline: Option<Instant>,
+    ) -> Result<UploadAttempts> {
+        // Last byte acknowledged in resumable mode; retries resume from here
+        let mut resume_offset = 0;
+
         let mut attempt = 0;
         let mut backoff_secs = self.retry_config.initial_backoff_secs;
+        // Every error carries the attempts so failed ones can be found in gateway logs
+        let mut attempts_made = UploadAttempts::default();
 
         loop {
             attempt += 1;
+            let correlation_id = uuid::Uuid::new_v4().to_string();
             debug!(
-                "Upload attempt {} of {}",
-                attempt, self.retry_config.max_attempts
+                "Upload attempt {} of {} (correlation id {})",
+                attempt, self.retry_config.max_attempts, correlation_id
             );
 
-            match self.try_upload(file_path, original_filename).await {
+            let started = Instant::now();
+            let offset_before = resume_offset;
+            let upload = self.try_upload(
+                endpoint,
+                source,
+                original_filename,
+                form_fields,
+                &mut resume_offset,
+                &correlation_id,
+            );
+            let result = match deadline {
+                Some(deadline) => timeout_at(deadline, upload).await.ok(),
+                None => Some(upload.await),
+            };
+
+            // Resumable attempts send only the chunks acknowledged since the last one
+            let bytes = match source {
+                UploadSource::Bytes { content, .. } if self.api_config.mode != "resumable" => {
+                    content.len() as u64
+                }
+                _ => resume_offset - offset_before,
+            };
+            let stats = UploadAttempt {
+                correlation_id: correlation_id.clone(),
+                bytes,
+                duration_ms: started.elapsed().as_millis() as u64,
+                success: matches!(result, Some(Ok(()))),
+            };
+            info!(
+                "Upload attempt {}: {} bytes in {}ms ({:.2} MB/s)",
+                attempt,
+                stats.bytes,
+                stats.duration_ms,
+                stats.megabytes_per_sec()
+            );
+            attempts_made.0.push(stats);
+
+            let Some(result) = result else {
+                return Err(self.deadline_exceeded(attempt, attempts_made));
+            };
+
+            match result {
                 Ok(()) => {
-                    info!("File uploaded successfully on attempt {}", attempt);
-                    return Ok(());
+                    info!(
+                        "File uploaded successfully on attempt {} (correlation id {})",
+                        attempt, correlation_id
+                    );
+                    return Ok(attempts_made);
                 }
                 Err(e) => {
-                    error!("Upload attempt {} failed: {}", attempt, e);
+                    error!(
+                        "Upload attempt {} failed (correlation id {}): {}",
+                        attempt,
+                        correlation_id,
+                        error_chain(&e)
+                    );
 
+                    // Keep the typed error (status and full body) in the chain
                     if attempt >= self.retry_config.max_attempts {
-                        anyhow::bail!(
-                            "Upload failed after {} attempts: {}",
-                            self.retry_config.max_attempts,
-                            e
+                        return Err(e.context(attempts_made).context(format!(
+                            "Upload failed after {} attempts",
+                            self.retry_config.max_attempts
+                        )));
+                    }
+
+                    let conflict_cleanup = self.api_config.cleanup_on_conflict
+                        && matches!(
+                            e.downcast_ref::<UploadError>(),
+                            Some(UploadError::Conflict { .. })
                         );
+
+                    // A failed POST may still have been imported; repeating it could duplicate records
+                    if !self.retry_config.idempotent_only
+                        && !self.is_idempotent()
+                        && (conflict_cleanup || self.is_retryable_error(&e))
+                    {
+                        return Err(e.context(attempts_made).context(format!(
+                            "Not retrying {} upload (retry.idempotent_only = false)",
+                            self.api_config.mode
+                        )));
                     }
 
+                    // A partial object from an interrupted upload blocks the retry until removed
+                    let retryable = match e.downcast_ref::<UploadError>() {
+                        Some(UploadError::Conflict { .. }) if conflict_cleanup => {
+                            if let Err(cleanup) = self
+                                .cleanup_partial(original_filename, &correlation_id)
+                                .await
+                            {
+                                return Err(e.context(attempts_made).context(format!(
+                                    "Cleanup after upload conflict failed: {:#}",
+                                    cleanup
+                                )));
+                            }
+                            true
+                        }
+                        _ => self.is_retryable_error(&e),
+                    };
+
                     // Determine if this is a retryable error
-                    if self.is_retryable_error(&e) {
+                    if retryable {
+                        // Honor the server's Retry-After when rate limited, up to the cap
+                        let wait_secs = match e.downcast_ref::<UploadError>() {
+                            Some(UploadError::RateLimited {
+                                retry_after_secs: Some(secs),
+                                ..
+                            }) => {
+                                let cap = self.retry_config.max_retry_after_secs;
+                                if *secs > cap {
+                                    warn!(
+                                        "Server asked to retry after {} seconds, capping the wait at retry.max_retry_after_secs ({})",
+                                        secs, cap
+                                    );
+                                }
+                                (*secs).min(cap)
+                            }
+                            _ => backoff_secs,
+                        };
+                        if let Some(deadline) = deadline {
+                            if Instant::now() + Duration::from_secs(wait_secs) >= deadline {
+                                error!("No time left in the upload budget for another attempt");
+                                return Err(self.deadline_exceeded(attempt, attempts_made));
+                            }
+                        }
                         warn!(
                             "Retryable error, waiting {} seconds before retry",
-                            backoff_secs
+                            wait_secs
                         );
-                        sleep(Duration::from_secs(backoff_secs)).await;
+                        sleep(Duration::from_secs(wait_secs)).await;
 
                         // Exponential backoff with cap at 30 seconds
                         backoff_secs = (backoff_secs * 2).min(30);
                     } else {
-                        anyhow::bail!("Non-retryable error: {}", e);
+                        return Err(e
+                            .context(attempts_made)
+                            .context("Non-retryable upload error"));
                     }
                 }
             }
         }
     }
 
-    async fn try_upload(&self, file_path: &Path, original_filename: &str) -> Result<()> {
-        match self.api_config.mode.as_str() {
-            "multipart" => self.upload_multipart(file_path, original_filename).await,
-            "json_base64" => self.upload_json_base64(file_path, original_filename).await,
-            "lookup_enrich" => {
+    async fn try_upload(
+        &self,
+        endpoint: &str,
+        source: &UploadSource<'_>,
+        original_filename: &str,
+        form_fields: &[(String, String)],
+        resume_offset: &mut u64,
+        correlation_id: &str,
+    ) -> Result<()> {
+        let mut endpoint = endpoint.to_string();
+        let mut hops = 0;
+
+        loop {
+            let result = self
+                .send_upload(
+                    &endpoint,
+                    source,
+                    original_filename,
+                    form_fields,
+                    resume_offset,
+                    correlation_id,
+                )
+                .await;
+
+            let location = match result
+                .as_ref()
+                .err()
+                .and_then(|e| e.downcast_ref::<UploadError>())
+            {
+                Some(UploadError::Redirect {
+                    location: Some(location),
+                    ..
+                }) if self.api_config.follow_redirects && hops < MAX_REDIRECTS => location,
+                _ => return result,
+            };
+
+            let target = reqwest::Url::parse(&endpoint)
+                .and_then(|base| base.join(location))
+                .with_context(|| format!("Invalid redirect location: {}", location))?;
+            hops += 1;
+            info!("Following redirect to {} (hop {})", target, hops);
+            endpoint = target.to_string();
+        }
+    }
+
+    async fn send_upload(
+        &self,
+        endpoint: &str,
+        source: &UploadSource<'_>,
+        original_filename: &str,
+        form_fields: &[(String, String)],
+        resume_offset: &mut u64,
+        correlation_id: &str,
+    ) -> Result<()> {
+        let in_memory = match source {
+            UploadSource::Bytes {
+                content,
+                size,
+                modified,
+            } => Some((content.as_slice(), *size, *modified)),
+            UploadSource::File(_) => None,
+        };
+        match (self.api_config.mode.as_str(), in_memory) {
+            ("multipart", Some((content, _, _))) => {
+                self.upload_multipart(
+                    endpoint,
+                    content,
+                    original_filename,
+                    form_fields,
+                    correlation_id,
+                )
+                .await
+            }
+            ("json_base64", Some((content, size, modified))) => {
+                self.upload_json_base64(
+                    endpoint,
+                    content,
+                    size,
+                    modified,
+                    original_filename,
+                    form_fields,
+                    correlation_id,
+                )
+                .await
+            }
+            ("resumable", _) => {
+                self.upload_resumable(
+                    endpoint,
+                    source,
+                    original_filename,
+                    resume_offset,
+                    correlation_id,
+                )
+                .await
+            }
+            ("lookup_enrich", _) => {
                 anyhow::bail!(
                     "lookup_enrich mode should be handled by the lookup enricher, not the uploader"
                 );
             }
+            // file_source only streams from disk in resumable mode
+            ("multipart" | "json_base64", None) => {
+                anyhow::bail!(
+                    "{} uploads must be read into memory first",
+                    self.api_config.mode
+                )
+            }
             _ => anyhow::bail!("Invalid upload mode: {}", self.api_config.mode),
         }
     }
 
-    async fn upload_multipart(&self, file_path: &Path, original_filename: &str) -> Result<()> {
-        debug!("Uploading file as multipart: {}", file_path.display());
+    async fn upload_multipart(
+        &self,
+        endpoint: &str,
+        content: &[u8],
+        original_filename: &str,
+        form_fields: &[(String, String)],
+        correlation_id: &str,
+    ) -> Result<()> {
+        debug!("Uploading {} as multipart", original_filename);
 
-        // Read file content
-        let file_content = fs::read(file_path)
-            .await
-            .context("Failed to read file for multipart upload")?;
-
-        let file_part =
-            reqwest::multipart::Part::bytes(file_content).file_name(original_filename.to_string());
+        let mime = match &self.api_config.file_mime {
+            Some(mime) => mime.as_str(),
+            None => mime_for_filename(original_filename),
+        };
+        let file_part = reqwest::multipart::Part::bytes(content.to_vec())
+            .file_name(original_filename.to_string())
+            .mime_str(mime)
+            .with_context(|| format!("Invalid api.file_mime: {}", mime))?;
 
         let field_name = self.api_config.field_name.clone();
         let mut form = reqwest::multipart::Form::new().part(field_name, file_part);
 
-        // Add extra fields
-        for (key, value) in &self.api_config.extra_fields {
+        // Extra fields, then the ones given for this upload
+        for (key, value) in form_fields {
             form = form.text(key.clone(), value.clone());
         }
 
-        let mut request = self.client.post(&self.api_config.endpoint).multipart(form);
+        let mut request = self.client.post(endpoint).multipart(form);
 
         // Add authentication
-        request = self.add_auth(request);
+        request = self.prepare_request(request, correlation_id);
 
         let response = request
             .send()
             .await
-            .context("Failed to send multipart request")?;
+            .map_err(|source| UploadError::Transport {
+                what: "multipart request",
+                source,
+            })?;
 
         self.handle_response(response).await
     }
 
-    async fn upload_json_base64(&self, file_path: &Path, original_filename: &str) -> Result<()> {
-        debug!("Uploading file as JSON base64: {}", file_path.display());
+    #[allow(clippy::too_many_arguments)]
+    async fn upload_json_base64(
+        &self,
+        endpoint: &str,
+        content: &[u8],
+        size: u64,
+        modified: SystemTime,
+        original_filename: &str,
+        fields: &[(String, String)],
+        correlation_id: &str,
+    ) -> Result<()> {
+        debug!("Uploading {} as JSON base64", original_filename);
 
-        // Read file content
-        let file_content = fs::read(file_path)
-            .await
-            .context("Failed to read file for base64 encoding")?;
+        // Encode as base64, or embed text files as-is
+        let encoded_content = match self.api_config.json_encoding.as_str() {
+            "utf8" => String::from_utf8(content.to_vec()).map_err(|_| {
+                anyhow::anyhow!(
+                    "File {} is not valid UTF-8 text; it cannot be sent with api.json_encoding = \"utf8\"",
+                    original_filename
+                )
+            })?,
+            _ => general_purpose::STANDARD.encode(content),
+        };
 
-        // Encode as base64
-        let base64_content = general_purpose::STANDARD.encode(&file_content);
+        let size_text = size.to_string();
+        let modified = chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339();
 
         // Create JSON payload
-        let mut payload = json!({
-            self.api_config.json_filename_key.clone(): original_filename,
-            self.api_config.json_data_key.clone(): base64_content
-        });
+        let mut payload = match &self.json_template {
+            Some(template) => {
+                let values = [
+                    ("filename", original_filename),
+                    ("data", encoded_content.as_str()),
+                    ("size", size_text.as_str()),
+                    ("modified", modified.as_str()),
+                ];
+                self.render_template(template, &values, fields)?
+            }
+            None => {
+                let mut payload = json!({
+                    self.api_config.json_filename_key.clone(): original_filename,
+                    self.api_config.json_data_key.clone(): encoded_content
+                });
+
+                // Add extra fields to JSON
+                for (key, value) in fields {
+                    payload[key] = json!(value);
+                }
+                payload
+            }
+        };
 
-        // Add extra fields to JSON
-        for (key, value) in &self.api_config.extra_fields {
-            payload[key] = json!(value);
+        // Config validation rejects include_file_metadata with a template
+        if self.api_config.include_file_metadata {
+            payload[&self.api_config.json_size_key] = json!(size);
+            payload[&self.api_config.json_modified_key] = json!(modified);
         }
 
-        let mut request = self.client.post(&self.api_config.endpoint).json(&payload);
+        let body = serde_json::to_vec(&payload).context("Failed to serialize JSON payload")?;
+        self.check_body_size(body.len() as u64)?;
+
+        let mut request = self
+            .client
+            .post(endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body);
 
         // Add authentication
-        request = self.add_auth(request);
+        request = self.prepare_request(request, correlation_id);
 
         let response = request
             .send()
             .await
-            .context("Failed to send JSON request")?;
+            .map_err(|source| UploadError::Transport {
+                what: "JSON request",
+                source,
+            })?;
 
         self.handle_response(response).await
     }
 
+    /// Catches an oversized body before the gateway answers with a bare 413.
+    fn check_body_size(&self, size: u64) -> Result<()> {
+        let Some(limit) = self.api_config.max_body_bytes else {
+            return Ok(());
+        };
+        if size <= limit {
+            return Ok(());
+        }
+
+        let error = UploadError::BodyTooLarge { size, limit };
+        if self.api_config.body_limit_action == "warn" {
+            warn!("{}; sending anyway", error);
+            return Ok(());
+        }
+        Err(error.into())
+    }
+
+    /// Substitutes `{{filename}}`, `{{data}}`, `{{size}}`, `{{modified}}` (the
+    /// `values`), and `{{field:name}}` inside the template's string values.
+    fn render_template(
+        &self,
+        template: &Value,
+        values: &[(&str, &str)],
+        fields: &[(String, String)],
+    ) -> Result<Value> {
+        Ok(match template {
+            Value::String(text) => {
+                let mut rendered = String::with_capacity(text.len());
+                let mut rest = text.as_str();
+                while let Some(start) = rest.find("{{") {
+                    let end = rest[start..]
+                        .find("}}")
+                        .with_context(|| format!("Unclosed placeholder in template: {}", text))?;
+                    rendered.push_str(&rest[..start]);
+                    let name = rest[start + 2..start + end].trim();
+                    match values.iter().find(|(key, _)| *key == name) {
+                        Some((_, value)) => rendered.push_str(value),
+                        None => match name.strip_prefix("field:") {
+                            Some(field) => rendered.push_str(
+                                fields
+                                    .iter()
+                                    .find(|(key, _)| key == field)
+                                    .map(|(_, value)| value)
+                                    .with_context(|| {
+                                        format!(
+                                            "Template references unknown extra field: {}",
+                                            field
+                                        )
+                                    })?,
+                            ),
+                            None => anyhow::bail!("Unknown template placeholder: {{{{{}}}}}", name),
+                        },
+                    }
+                    rest = &rest[start + end + 2..];
+                }
+                rendered.push_str(rest);
+                Value::String(rendered)
+            }
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.render_template(item, values, fields))
+                    .collect::<Result<_>>()?,
+            ),
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, value)| {
+                        Ok((key.clone(), self.render_template(value, values, fields)?))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            other => other.clone(),
+        })
+    }
+
+    async fn upload_resumable(
+        &self,
+        endpoint: &str,
+        source: &UploadSource<'_>,
+        original_filename: &str,
+        resume_offset: &mut u64,
+        correlation_id: &str,
+    ) -> Result<()> {
+        // In-memory content is sliced; a file is read chunk by chunk
+        let (mut file, total) = match source {
+            UploadSource::File(file_path) => {
+                let file = fs::File::open(file_path)
+                    .await
+                    .context("Failed to open file for resumable upload")?;
+                let total = file
+                    .metadata()
+                    .await
+                    .context("Failed to read file metadata for resumable upload")?
+                    .len();
+                (Some(file), total)
+            }
+            UploadSource::Bytes { content, .. } => (None, content.len() as u64),
+        };
+
+        let mut offset = *resume_offset;
+        if offset > 0 {
+            info!(
+                "Resuming upload of {} at byte {} of {}",
+                original_filename, offset, total
+            );
+        }
+
+        // Nothing to chunk, send a single empty PUT
+        if total == 0 {
+            let request = self
+                .resumable_request(endpoint, original_filename)
+                .body(Vec::new());
+            let response = self
+                .prepare_request(request, correlation_id)
+                .send()
+                .await
+                .map_err(|source| UploadError::Transport {
+                    what: "resumable request",
+                    source,
+                })?;
+            return self.handle_response(response).await;
+        }
+
+        while offset < total {
+            let len = self.api_config.chunk_size_bytes.min(total - offset);
+            let end = offset + len - 1;
+
+            let chunk = match (file.as_mut(), source) {
+                (Some(file), _) => {
+                    file.seek(SeekFrom::Start(offset))
+                        .await
+                        .context("Failed to seek in file for resumable upload")?;
+                    let mut chunk = vec![0u8; len as usize];
+                    file.read_exact(&mut chunk)
+                        .await
+                        .context("Failed to read chunk for resumable upload")?;
+                    chunk
+                }
+                (None, UploadSource::Bytes { content, .. }) => {
+                    content[offset as usize..=end as usize].to_vec()
+                }
+                (None, UploadSource::File(_)) => unreachable!("files are opened above"),
+            };
+
+            debug!("Uploading bytes {}-{}/{}", offset, end, total);
+            let request = self
+                .resumable_request(endpoint, original_filename)
+                .header(
+                    reqwest::header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", offset, end, total),
+                )
+                .body(chunk);
+
+            let response = self
+                .prepare_request(request, correlation_id)
+                .send()
+                .await
+                .map_err(|source| UploadError::Transport {
+                    what: "resumable chunk",
+                    source,
+                })?;
+
+            let status = response.status();
+            if !status.is_success() && status != StatusCode::PERMANENT_REDIRECT {
+                return self.handle_response(response).await;
+            }
+
+            // Prefer the server's view of what it has received (Range: bytes=0-N), as
+            // long as it moves forward and stays within the file
+            let acknowledged = response
+                .headers()
+                .get(reqwest::header::RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.rsplit('-').next())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(|last| last.saturating_add(1))
+                .unwrap_or(end + 1);
+            if acknowledged <= offset || acknowledged > total {
+                return Err(UploadError::InvalidRange {
+                    offset,
+                    acknowledged,
+                    total,
+                }
+                .into());
+            }
+            offset = acknowledged;
+            *resume_offset = offset;
+        }
+
+        info!("Resumable upload complete ({} bytes)", total);
+        Ok(())
+    }
+
+    /// DELETEs the partial object left by an interrupted upload, for `api.cleanup_on_conflict`.
+    async fn cleanup_partial(&self, filename: &str, correlation_id: &str) -> Result<()> {
+        let endpoint = &self.api_config.cleanup_endpoint;
+        warn!(
+            "Upload conflict, removing partial {} via {}",
+            filename, endpoint
+        );
+        let request = self
+            .client
+            .delete(endpoint)
+            .query(&[("filename", filename)]);
+        let response = self
+            .prepare_request(request, correlation_id)
+            .send()
+            .await
+            .map_err(|source| UploadError::Transport {
+                what: "cleanup request",
+                source,
+            })?;
+
+        // Nothing left to remove is as good as removed
+        let status = response.status();
+        if status.is_success() || status == StatusCode::NOT_FOUND {
+            info!("Removed partial upload {} ({})", filename, status);
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Cleanup request failed with status {}: {}",
+            status,
+            response.text().await.unwrap_or_default()
+        )
+    }
+
+    /// The deadline is the headline; the attempts sit underneath it.
+    fn deadline_exceeded(&self, attempts: u32, attempts_made: UploadAttempts) -> anyhow::Error {
+        anyhow::Error::msg(attempts_made).context(UploadError::DeadlineExceeded {
+            budget_secs: self.api_config.total_timeout_secs.unwrap_or_default(),
+            attempts,
+        })
+    }
+
+    fn resumable_request(
+        &self,
+        endpoint: &str,
+        original_filename: &str,
+    ) -> reqwest::RequestBuilder {
+        let quoted = original_filename.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut request = self.client.put(endpoint).header(
+            reqwest::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", quoted),
+        );
+        if let Some(etag) = &self.api_config.if_match {
+            request = request.header(reqwest::header::IF_MATCH, etag);
+        }
+        if let Some(etag) = &self.api_config.if_none_match {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        request
+    }
+
+    fn sanitize_filename(&self, filename: &str) -> String {
+        filename
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || self.api_config.filename_allowed_chars.contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+
+    /// Adds auth and the correlation header to an upload request.
+    fn prepare_request(
+        &self,
+        request: reqwest::RequestBuilder,
+        correlation_id: &str,
+    ) -> reqwest::RequestBuilder {
+        let request = self.add_auth(request);
+        if self.api_config.correlation_header.is_empty() {
+            return request;
+        }
+        request.header(self.api_config.correlation_header.as_str(), correlation_id)
+    }
+
     fn add_auth(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
         match self.api_config.auth.as_str() {
-            "bearer" => request.bearer_auth(&self.api_config.bearer_token),
+            "bearer" => request.bearer_auth(self.bearer_token.read().unwrap().as_str()),
             "basic" => request.basic_auth(
                 &self.api_config.basic_username,
                 Some(&self.api_config.basic_password),
@@ -190,6 +1257,17 @@ impl Uploader {
 
     async fn handle_response(&self, response: reqwest::Response) -> Result<()> {
         let status = response.status();
+        // Only the delta-seconds form; an HTTP-date falls back to the usual backoff
+        let retry_after_secs = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok());
+        let response_location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
         let response_text = response
             .text()
             .await
@@ -199,22 +1277,107 @@ impl Uploader {
 
         match status {
             StatusCode::OK | StatusCode::CREATED | StatusCode::ACCEPTED => {
+                self.check_response_schema(&response_text)?;
                 info!("Upload successful (status: {})", status);
                 Ok(())
             }
-            status if status.is_client_error() => {
-                anyhow::bail!("Client error ({}): {}", status, response_text);
+            StatusCode::TOO_MANY_REQUESTS => {
+                warn!(
+                    "Rate limited by server (Retry-After: {})",
+                    retry_after_secs
+                        .map(|s| format!("{}s", s))
+                        .unwrap_or_else(|| "not set".to_string())
+                );
+                Err(UploadError::RateLimited {
+                    status,
+                    retry_after_secs,
+                    body: response_text,
+                }
+                .into())
             }
-            status if status.is_server_error() => {
-                anyhow::bail!("Server error ({}): {}", status, response_text);
+            status if status.is_redirection() => {
+                let location = response_location;
+                warn!(
+                    "Upload endpoint redirected ({}) to {}",
+                    status,
+                    location.as_deref().unwrap_or("<no location>")
+                );
+                Err(UploadError::Redirect { status, location }.into())
             }
-            _ => {
-                anyhow::bail!("Unexpected status code: {} - {}", status, response_text);
+            StatusCode::CONFLICT => Err(UploadError::Conflict {
+                body: response_text,
             }
+            .into()),
+            StatusCode::PRECONDITION_FAILED => {
+                warn!("Conditional upload rejected: the resource was updated concurrently");
+                Err(UploadError::PreconditionFailed {
+                    body: response_text,
+                }
+                .into())
+            }
+            status if status.is_client_error() => Err(UploadError::Client {
+                status,
+                body: response_text,
+            }
+            .into()),
+            status if status.is_server_error() => Err(UploadError::Server {
+                status,
+                body: response_text,
+            }
+            .into()),
+            _ => Err(UploadError::Unexpected {
+                status,
+                body: response_text,
+            }
+            .into()),
         }
     }
 
+    /// Checks a successful response body against `api.response_schema`, if set.
+    fn check_response_schema(&self, body: &str) -> Result<(), UploadError> {
+        let Some(schema) = &self.response_schema else {
+            return Ok(());
+        };
+        let reason = match serde_json::from_str::<Value>(body) {
+            Ok(value) => {
+                let violations: Vec<String> = schema
+                    .iter_errors(&value)
+                    .map(|e| {
+                        format!(
+                            "{} at /{}",
+                            e,
+                            e.instance_path().to_string().trim_start_matches('/')
+                        )
+                    })
+                    .collect();
+                if violations.is_empty() {
+                    return Ok(());
+                }
+                violations.join("; ")
+            }
+            Err(e) => format!("body is not JSON: {}", e),
+        };
+        warn!(
+            "Upload response rejected by api.response_schema: {}",
+            reason
+        );
+        Err(UploadError::InvalidResponse {
+            reason,
+            body: body.to_string(),
+        })
+    }
+
+    /// Resumable chunks are PUTs to a fixed byte range, so resending one is harmless.
+    fn is_idempotent(&self) -> bool {
+        self.api_config.mode == "resumable"
+    }
+
     fn is_retryable_error(&self, error: &anyhow::Error) -> bool {
+        // Status-based errors carry their own classification
+        if let Some(upload_error) = error.downcast_ref::<UploadError>() {
+            return upload_error.is_retryable(self.is_idempotent());
+        }
+
         let error_str = error.to_string().to_lowercase();
 
         // Retry on network errors, timeouts, and 5xx server errors
@@ -226,9 +1389,187 @@ impl Uploader {
     }
 }
 
+fn load_response_schema(path: &str) -> Result<jsonschema::Validator> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read api.response_schema: {}", path))?;
+    let schema: Value = serde_json::from_str(&content)
+        .with_context(|| format!("api.response_schema is not valid JSON: {}", path))?;
+    jsonschema::validator_for(&schema).map_err(|e| {
+        anyhow::anyhow!(
+            "api.response_schema is not a valid JSON Schema ({}): {}",
+            path,
+            e
+        )
+    })
+}
+
+/// Fills `{name}` placeholders from the captures; unknown names are kept as written.
+fn expand_captures(value: &str, captures: &[(String, String)]) -> String {
+    let mut expanded = value.to_string();
+    for (name, capture) in captures {
+        expanded = expanded.replace(&format!("{{{}}}", name), capture);
+    }
+    expanded
+}
+
+/// Pins the HTTP version and minimum TLS version for servers that mishandle
+/// ALPN or newer protocols. Both values are checked by config validation.
+pub fn apply_protocol(
+    builder: reqwest::ClientBuilder,
+    http_version: &str,
+    min_tls_version: Option<&str>,
+) -> reqwest::ClientBuilder {
+    let builder = match http_version {
+        "http1" => builder.http1_only(),
+        "http2" => builder.http2_prior_knowledge(),
+        _ => builder,
+    };
+    match min_tls_version {
+        Some("1.3") => builder.min_tls_version(reqwest::tls::Version::TLS_1_3),
+        Some(_) => builder.min_tls_version(reqwest::tls::Version::TLS_1_2),
+        None => builder,
+    }
+}
+
+/// Sends HEAD, then OPTIONS if the server only routes other methods (405).
+pub async fn probe_endpoint(
+    build: impl Fn(reqwest::Method) -> reqwest::RequestBuilder,
+) -> reqwest::Result<StatusCode> {
+    let status = build(reqwest::Method::HEAD).send().await?.status();
+    if status != StatusCode::METHOD_NOT_ALLOWED {
+        return Ok(status);
+    }
+    Ok(build(reqwest::Method::OPTIONS).send().await?.status())
+}
+
+/// Content type for common report extensions; anything else is sent as binary.
+fn mime_for_filename(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("tsv") => "text/tab-separated-values",
+        Some("csv") => "text/csv",
+        Some("txt") => "text/plain",
+        Some("json") => "application/json",
+        Some("gz") => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Runs the token command through the platform shell.
+fn token_command(command: &str) -> (&'static str, [&str; 2]) {
+    if cfg!(windows) {
+        ("cmd", ["/C", command])
+    } else {
+        ("sh", ["-c", command])
+    }
+}
+
+fn token_from_output(command: &str, output: std::process::Output) -> Result<String> {
+    if !output.status.success() {
+        anyhow::bail!(
+            "Bearer token command `{}` failed ({}): {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let token = String::from_utf8(output.stdout)
+        .context("Bearer token command printed non-UTF-8 output")?
+        .trim()
+        .to_string();
+    if token.is_empty() {
+        anyhow::bail!("Bearer token command `{}` printed an empty token", command);
+    }
+    Ok(token)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves the given raw HTTP responses in order (repeating the last one)
+    /// and records every request received.
+    async fn spawn_server(responses: Vec<&'static str>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(_) => return,
+                };
+
+                let next = log.lock().unwrap().len();
+                let response = responses[next.min(responses.len() - 1)];
+
+                // Read headers and body so the client sees a clean exchange
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                loop {
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(header_end) = text.find("\r\n\r\n") {
+                        if response == DROPPED_CONNECTION {
+                            break;
+                        }
+                        let content_length = text[..header_end]
+                            .lines()
+                            .find_map(|l| {
+                                let (name, value) = l.split_once(':')?;
+                                name.eq_ignore_ascii_case("content-length")
+                                    .then(|| value.trim().parse::<usize>().ok())?
+                            })
+                            .unwrap_or(0);
+                        if request.len() >= header_end + 4 + content_length {
+                            break;
+                        }
+                    }
+                }
+
+                log.lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request).to_string());
+                if response == DROPPED_CONNECTION {
+                    continue;
+                }
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        (format!("http://{}/upload", addr), received)
+    }
+
+    fn create_test_file(content: &str) -> tempfile::NamedTempFile {
+        use std::io::Write;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        file.flush().unwrap();
+        file
+    }
+
+    /// Closes the socket after the request headers, without reading the rest or answering
+    const DROPPED_CONNECTION: &str = "";
+    const OK_RESPONSE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+    const RATE_LIMITED_RESPONSE: &str = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 4\r\nConnection: close\r\n\r\nslow";
+    const SERVER_ERROR_RESPONSE: &str =
+        "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 4\r\nConnection: close\r\n\r\nfail";
+    const BAD_REQUEST_RESPONSE: &str =
+        "HTTP/1.1 400 Bad Request\r\nContent-Length: 3\r\nConnection: close\r\n\r\nbad";
 
     fn create_test_config() -> (ApiConfig, RetryConfig) {
         let api_config = ApiConfig {
@@ -242,11 +1583,44 @@ mod tests {
             bearer_token: String::new(),
             basic_username: String::new(),
             basic_password: String::new(),
+            chunk_size_bytes: 4,
+            include_file_metadata: false,
+            json_size_key: "size".to_string(),
+            json_modified_key: "modified".to_string(),
+            fallback_endpoint: None,
+            upload_concurrency: 1,
+            json_template: None,
+            response_schema: None,
+            override_filename: None,
+            sanitize_filename: false,
+            filename_allowed_chars: "._-".to_string(),
+            follow_redirects: false,
+            attempt_timeout_secs: 30,
+            total_timeout_secs: None,
+            json_encoding: "base64".to_string(),
+            pool_idle_timeout_secs: 600,
+            pool_max_idle_per_host: 4,
+            bearer_token_command: None,
+            bearer_token_refresh: false,
+            max_body_bytes: None,
+            body_limit_action: "fail".to_string(),
+            if_match: None,
+            if_none_match: None,
+            file_mime: None,
+            correlation_header: "X-Correlation-Id".to_string(),
+            sink_dir: String::new(),
+            cleanup_on_conflict: false,
+            cleanup_endpoint: String::new(),
+            http_version: "auto".to_string(),
+            min_tls_version: None,
         };
 
         let retry_config = RetryConfig {
             max_attempts: 3,
             initial_backoff_secs: 1,
+            idempotent_only: true,
+            circuit_break_after: None,
+            max_retry_after_secs: 60,
         };
 
         (api_config, retry_config)
@@ -273,4 +1647,941 @@ mod tests {
         assert!(!uploader.is_retryable_error(&anyhow::anyhow!("Client error 400")));
         assert!(!uploader.is_retryable_error(&anyhow::anyhow!("Invalid file format")));
     }
+
+    #[tokio::test]
+    async fn test_pinned_http1_client_uploads() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.http_version = "http1".to_string();
+        api_config.min_tls_version = Some("1.2".to_string());
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap();
+        assert!(received.lock().unwrap()[0].contains(" HTTP/1.1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_file_mode_writes_to_sink_dir() {
+        let sink = tempfile::tempdir().unwrap();
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.mode = "file".to_string();
+        api_config.sink_dir = sink.path().join("outbox").to_string_lossy().to_string();
+        api_config.sanitize_filename = true;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let file = create_test_file("0123456789");
+
+        let attempts = uploader
+            .upload_file(file.path(), "report (ALL).txt", &[], &[])
+            .await
+            .unwrap();
+        assert!(attempts.0.is_empty());
+        let written = sink.path().join("outbox").join("report__ALL_.txt");
+        assert_eq!(std::fs::read_to_string(&written).unwrap(), "0123456789");
+
+        // A second delivery of the same name replaces the first
+        let file = create_test_file("abc");
+        uploader
+            .upload_file(file.path(), "report (ALL).txt", &[], &[])
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&written).unwrap(), "abc");
+        assert_eq!(
+            std::fs::read_dir(sink.path().join("outbox"))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_override_filename() {
+        let sink = tempfile::tempdir().unwrap();
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.mode = "file".to_string();
+        api_config.sink_dir = sink.path().to_string_lossy().to_string();
+        api_config.override_filename = Some("plant{plant}_inbound_{date}.tsv".to_string());
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let file = create_test_file("0123456789");
+
+        let captures = [("plant".to_string(), "149".to_string())];
+        uploader
+            .upload_file(file.path(), "20250115_y_149.txt", &[], &captures)
+            .await
+            .unwrap();
+        let expected = format!(
+            "plant149_inbound_{}.tsv",
+            chrono::Local::now().format("%Y%m%d")
+        );
+        assert!(sink.path().join(expected).exists());
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.sanitize_filename = true;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        assert_eq!(
+            uploader.sanitize_filename("20250115 y_149 (ALL).txt"),
+            "20250115_y_149__ALL_.txt"
+        );
+        assert_eq!(
+            uploader.sanitize_filename("20250115_y_149-ALL.txt"),
+            "20250115_y_149-ALL.txt"
+        );
+    }
+
+    #[test]
+    fn test_render_json_template() {
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.json_template = Some(
+            r#"{"meta":{"name":"{{filename}}","size":"{{size}}","tenant":"t-{{field:tenant}}"},"payload":{"content":"{{data}}"}}"#
+                .to_string(),
+        );
+        api_config
+            .extra_fields
+            .insert("tenant".to_string(), "A".to_string());
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let template = uploader.json_template.clone().unwrap();
+        let fields = uploader.upload_fields(&[], &[]);
+        let values = [("filename", "report.txt"), ("data", "QUJD"), ("size", "3")];
+        let rendered = uploader
+            .render_template(&template, &values, &fields)
+            .unwrap();
+
+        assert_eq!(
+            rendered,
+            json!({"meta": {"name": "report.txt", "size": "3", "tenant": "t-A"}, "payload": {"content": "QUJD"}})
+        );
+        assert!(uploader
+            .render_template(&json!("{{field:missing}}"), &values, &fields)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_is_retried() {
+        let (endpoint, received) = spawn_server(vec![RATE_LIMITED_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("Plant\tDelivery\tMaterial\n");
+        let result = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(received.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_long_retry_after_is_capped() {
+        const DAY_LONG_RESPONSE: &str = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 86400\r\nContent-Length: 4\r\nConnection: close\r\n\r\nslow";
+        let (endpoint, received) = spawn_server(vec![DAY_LONG_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        retry_config.max_retry_after_secs = 0;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("Plant\tDelivery\tMaterial\n");
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            uploader.upload_file(file.path(), "test.txt", &[], &[]),
+        )
+        .await
+        .expect("Retry-After should be capped");
+
+        assert!(result.is_ok());
+        assert_eq!(received.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_bad_request_is_not_retried() {
+        let (endpoint, received) = spawn_server(vec![BAD_REQUEST_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("Plant\tDelivery\tMaterial\n");
+        let result = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resumable_retry_resumes_from_acknowledged_offset() {
+        let (endpoint, received) = spawn_server(vec![
+            OK_RESPONSE,
+            SERVER_ERROR_RESPONSE,
+            OK_RESPONSE,
+            OK_RESPONSE,
+        ])
+        .await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.mode = "resumable".to_string();
+        retry_config.initial_backoff_secs = 0;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        let result = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await;
+        assert!(result.is_ok());
+
+        let ranges: Vec<String> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|r| {
+                r.lines()
+                    .find(|l| l.to_lowercase().starts_with("content-range:"))
+                    .map(|l| l.split_once(':').unwrap().1.trim().to_string())
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                "bytes 0-3/10",
+                "bytes 4-7/10",
+                "bytes 4-7/10",
+                "bytes 8-9/10"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resumable_retry_after_dropped_connection() {
+        let (endpoint, received) = spawn_server(vec![
+            OK_RESPONSE,
+            DROPPED_CONNECTION,
+            OK_RESPONSE,
+            OK_RESPONSE,
+        ])
+        .await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.mode = "resumable".to_string();
+        retry_config.initial_backoff_secs = 0;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap();
+
+        let ranges: Vec<String> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|r| {
+                r.lines()
+                    .find(|l| l.to_lowercase().starts_with("content-range:"))
+                    .map(|l| l.split_once(':').unwrap().1.trim().to_string())
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                "bytes 0-3/10",
+                "bytes 4-7/10",
+                "bytes 4-7/10",
+                "bytes 8-9/10"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resumable_rejects_range_that_does_not_advance() {
+        const STALLED_RESPONSE: &str = "HTTP/1.1 308 Permanent Redirect\r\nRange: bytes=0-0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        const OVERSHOOT_RESPONSE: &str = "HTTP/1.1 308 Permanent Redirect\r\nRange: bytes=0-20\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+        for (response, requests) in [(STALLED_RESPONSE, 2), (OVERSHOOT_RESPONSE, 1)] {
+            let (endpoint, received) = spawn_server(vec![response]).await;
+            let (mut api_config, retry_config) = create_test_config();
+            api_config.endpoint = endpoint;
+            api_config.mode = "resumable".to_string();
+            let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+            let file = create_test_file("0123456789");
+            let error = uploader
+                .upload_file(file.path(), "say \"hi\".txt", &[], &[])
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<UploadError>(),
+                Some(UploadError::InvalidRange { total: 10, .. })
+            ));
+
+            let received = received.lock().unwrap();
+            assert_eq!(received.len(), requests);
+            assert!(received[0].contains("filename=\"say \\\"hi\\\".txt\""));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_base64_includes_file_metadata() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.mode = "json_base64".to_string();
+        api_config.include_file_metadata = true;
+        api_config.json_size_key = "file_size".to_string();
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap();
+
+        let request = received.lock().unwrap()[0].clone();
+        let body = request.split("\r\n\r\n").nth(1).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(payload["file_size"], json!(10));
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(payload["modified"].as_str().unwrap()).is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_derived_file_sends_source_metadata() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.mode = "json_base64".to_string();
+        api_config.include_file_metadata = true;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let source = create_test_file("Plant\tDelivery\tMaterial\n");
+        let extracted_at = chrono::DateTime::parse_from_rfc3339("2025-01-15T14:30:22+00:00")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        source
+            .as_file()
+            .set_modified(SystemTime::from(extracted_at))
+            .unwrap();
+        let transformed = create_test_file("Plant\n");
+        uploader
+            .upload_derived_file(transformed.path(), source.path(), "test.txt", &[], &[])
+            .await
+            .unwrap();
+
+        let request = received.lock().unwrap()[0].clone();
+        let body = request.split("\r\n\r\n").nth(1).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(payload["size"], json!(24));
+        assert_eq!(payload["modified"], json!(extracted_at.to_rfc3339()));
+    }
+
+    #[tokio::test]
+    async fn test_json_utf8_encoding_embeds_text() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.mode = "json_base64".to_string();
+        api_config.json_encoding = "utf8".to_string();
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("Plant\tDelivery\n");
+        uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap();
+
+        let request = received.lock().unwrap()[0].clone();
+        let body = request.split("\r\n\r\n").nth(1).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(payload["data"], json!("Plant\tDelivery\n"));
+    }
+
+    #[tokio::test]
+    async fn test_json_body_over_limit() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.mode = "json_base64".to_string();
+        api_config.max_body_bytes = Some(16);
+
+        let file = create_test_file("0123456789");
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let err = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<UploadError>(),
+            Some(UploadError::BodyTooLarge { limit: 16, .. })
+        ));
+        assert!(received.lock().unwrap().is_empty());
+
+        api_config.body_limit_action = "warn".to_string();
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap();
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let (endpoint, received) = spawn_server(vec![SERVER_ERROR_RESPONSE]).await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        retry_config.max_attempts = 1;
+        retry_config.circuit_break_after = Some(2);
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let file = create_test_file("0123456789");
+
+        for _ in 0..2 {
+            uploader
+                .upload_file(file.path(), "test.txt", &[], &[])
+                .await
+                .unwrap_err();
+        }
+        assert!(uploader.check_circuit().is_err());
+        let err = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<UploadError>(),
+            Some(UploadError::CircuitOpen { failures: 2 })
+        ));
+        assert_eq!(received.lock().unwrap().len(), 2);
+
+        uploader.reset_circuit();
+        uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+        assert_eq!(received.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_transport_error_keeps_source_chain() {
+        // Bind and drop a listener so the port refuses connections
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = format!("http://{}/upload", addr);
+        retry_config.max_attempts = 1;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let file = create_test_file("0123456789");
+
+        let err = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+        match err.downcast_ref::<UploadError>() {
+            Some(upload_error @ UploadError::Transport { source, .. }) => {
+                assert!(source.is_connect());
+                assert!(upload_error.is_retryable(false));
+            }
+            other => panic!("expected a transport error, got {:?}", other),
+        }
+        let chain = error_chain(&err);
+        assert!(chain.contains("Failed to send multipart request"));
+        assert!(chain.contains("caused by: error sending request"));
+    }
+
+    #[tokio::test]
+    async fn test_connection_reset_is_retried_only_when_idempotent() {
+        let (endpoint, received) = spawn_server(vec![DROPPED_CONNECTION]).await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        retry_config.max_attempts = 1;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let file = create_test_file("0123456789");
+
+        let err = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+        match err.downcast_ref::<UploadError>() {
+            Some(upload_error @ UploadError::Transport { source, .. }) => {
+                assert!(!source.is_timeout() && !source.is_connect());
+                assert!(!upload_error.is_retryable(false));
+                assert!(upload_error.is_retryable(true));
+            }
+            other => panic!("expected a transport error, got {:?}", other),
+        }
+        assert!(!uploader.is_retryable_error(&err));
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_only_false_skips_post_retries() {
+        let (endpoint, received) = spawn_server(vec![SERVER_ERROR_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        retry_config.initial_backoff_secs = 0;
+        retry_config.idempotent_only = false;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        let err = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("retry.idempotent_only = false"));
+        assert_eq!(received.lock().unwrap().len(), 1);
+
+        // A client error names its own reason rather than the setting
+        let (endpoint, _received) = spawn_server(vec![BAD_REQUEST_RESPONSE]).await;
+        api_config.endpoint = endpoint;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let err = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("Non-retryable upload error"));
+        assert!(!message.contains("retry.idempotent_only"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_bytes_without_a_file() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        uploader
+            .upload_bytes(b"generated,content".to_vec(), "generated.csv")
+            .await
+            .unwrap();
+
+        let request = received.lock().unwrap()[0].clone();
+        assert!(request.contains("filename=\"generated.csv\""));
+        assert!(request.contains("generated,content"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_bytes_resumable_chunks() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE, OK_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.mode = "resumable".to_string();
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        uploader
+            .upload_bytes(b"0123456789".to_vec(), "test.txt")
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        assert!(received[2].ends_with("89"));
+    }
+
+    #[tokio::test]
+    async fn test_multipart_file_mime() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        uploader
+            .upload_bytes(b"a\tb".to_vec(), "report.TSV")
+            .await
+            .unwrap();
+
+        api_config.file_mime = Some("text/tab-separated-values".to_string());
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        uploader
+            .upload_bytes(b"a\tb".to_vec(), "report.txt")
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        for request in received.iter() {
+            assert!(request.contains("Content-Type: text/tab-separated-values"));
+        }
+        assert_eq!(mime_for_filename("report.dat"), "application/octet-stream");
+    }
+
+    #[tokio::test]
+    async fn test_multipart_form_fields() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let file = create_test_file("a\tb\n");
+
+        let fields = vec![(
+            "transform_report".to_string(),
+            "{\"data_rows\":1}".to_string(),
+        )];
+        uploader
+            .upload_file(file.path(), "test.txt", &fields, &[])
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert!(received[0].contains("name=\"transform_report\"\r\n\r\n{\"data_rows\":1}"));
+    }
+
+    #[tokio::test]
+    async fn test_response_schema_rejects_unexpected_body() {
+        const QUEUED_RESPONSE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 33\r\nConnection: close\r\n\r\n{\"import_id\":7,\"status\":\"queued\"}";
+        let schema = create_test_file(
+            r#"{"type":"object","required":["import_id","status"],
+                "properties":{"import_id":{"type":"integer"},"status":{"const":"queued"}}}"#,
+        );
+        let (endpoint, _received) = spawn_server(vec![QUEUED_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.response_schema = Some(schema.path().to_string_lossy().to_string());
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        uploader
+            .upload_bytes(b"data".to_vec(), "test.txt")
+            .await
+            .unwrap();
+
+        // A 200 with a body that is not the expected JSON fails without a retry
+        let err = uploader
+            .upload_bytes(b"data".to_vec(), "test.txt")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<UploadError>(),
+            Some(UploadError::InvalidResponse { reason, body })
+                if reason.starts_with("body is not JSON") && body == "ok"
+        ));
+        let attempts = err.downcast_ref::<UploadAttempts>().unwrap();
+        assert_eq!(attempts.0.len(), 1);
+
+        let violation = uploader
+            .check_response_schema(r#"{"import_id":7,"status":"failed"}"#)
+            .unwrap_err();
+        assert!(violation.to_string().contains("at /status"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_response_does_not_fail_over() {
+        let schema = create_test_file(r#"{"type":"object","required":["import_id"]}"#);
+        let (primary, primary_received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (fallback, fallback_received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = primary;
+        api_config.fallback_endpoint = Some(fallback);
+        api_config.response_schema = Some(schema.path().to_string_lossy().to_string());
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let err = uploader
+            .upload_bytes(b"data".to_vec(), "test.txt")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<UploadError>(),
+            Some(UploadError::InvalidResponse { .. })
+        ));
+        assert_eq!(primary_received.lock().unwrap().len(), 1);
+        assert!(fallback_received.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_extra_fields_expand_captures() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config
+            .extra_fields
+            .insert("plant".to_string(), "P{plant}".to_string());
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let file = create_test_file("a\tb\n");
+
+        let captures = vec![("plant".to_string(), "149".to_string())];
+        uploader
+            .upload_file(file.path(), "y_149.txt", &[], &captures)
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert!(received[0].contains("name=\"plant\"\r\n\r\nP149\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_correlation_id_per_attempt() {
+        let (endpoint, received) = spawn_server(vec![SERVER_ERROR_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        retry_config.initial_backoff_secs = 0;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let attempts = uploader
+            .upload_bytes(b"data".to_vec(), "test.txt")
+            .await
+            .unwrap();
+
+        let ids: Vec<&str> = attempts.correlation_ids().collect();
+        assert_eq!(ids.len(), 2);
+        assert_ne!(ids[0], ids[1]);
+        assert!(!attempts.0[0].success);
+        assert!(attempts.0[1].success);
+        assert!(attempts.0.iter().all(|attempt| attempt.bytes == 4));
+        let received = received.lock().unwrap();
+        for (request, id) in received.iter().zip(ids) {
+            let expected = format!("x-correlation-id: {}", id);
+            assert!(request.to_lowercase().contains(&expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_falls_back_to_options() {
+        const NOT_ALLOWED_RESPONSE: &str =
+            "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let (endpoint, received) = spawn_server(vec![NOT_ALLOWED_RESPONSE, OK_RESPONSE]).await;
+        let (api_config, retry_config) = create_test_config();
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let status = uploader.probe(&endpoint).await.unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert!(received[0].starts_with("HEAD /upload"));
+        assert!(received[1].starts_with("OPTIONS /upload"));
+    }
+
+    #[tokio::test]
+    async fn test_correlation_ids_kept_on_failure() {
+        let (endpoint, _received) = spawn_server(vec![BAD_REQUEST_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let err = uploader
+            .upload_bytes(b"data".to_vec(), "test.txt")
+            .await
+            .unwrap_err();
+
+        let attempts = err.downcast_ref::<UploadAttempts>().unwrap();
+        assert_eq!(attempts.0.len(), 1);
+        assert!(format!("{:#}", err).contains(&attempts.0[0].correlation_id));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_endpoint_used_after_primary_fails() {
+        let (primary, primary_received) = spawn_server(vec![SERVER_ERROR_RESPONSE]).await;
+        let (fallback, fallback_received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = primary;
+        api_config.fallback_endpoint = Some(fallback);
+        retry_config.initial_backoff_secs = 0;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        let result = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(primary_received.lock().unwrap().len(), 3);
+        assert_eq!(fallback_received.lock().unwrap().len(), 1);
+    }
+
+    const PRECONDITION_FAILED_RESPONSE: &str =
+        "HTTP/1.1 412 Precondition Failed\r\nContent-Length: 5\r\nConnection: close\r\n\r\nstale";
+
+    #[tokio::test]
+    async fn test_conditional_put_precondition_failed() {
+        let (endpoint, received) = spawn_server(vec![PRECONDITION_FAILED_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.mode = "resumable".to_string();
+        api_config.if_match = Some("\"v1\"".to_string());
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        let err = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<UploadError>(),
+            Some(UploadError::PreconditionFailed { body }) if body == "stale"
+        ));
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].to_lowercase().contains("if-match: \"v1\""));
+    }
+
+    #[tokio::test]
+    async fn test_conflict_cleans_up_then_retries() {
+        const CONFLICT_RESPONSE: &str =
+            "HTTP/1.1 409 Conflict\r\nContent-Length: 7\r\nConnection: close\r\n\r\npartial";
+        let (endpoint, received) = spawn_server(vec![CONFLICT_RESPONSE, OK_RESPONSE]).await;
+        let (cleanup, cleanup_received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.cleanup_on_conflict = true;
+        api_config.cleanup_endpoint = cleanup;
+        retry_config.initial_backoff_secs = 0;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        uploader
+            .upload_bytes(b"data".to_vec(), "report 1.txt")
+            .await
+            .unwrap();
+
+        assert_eq!(received.lock().unwrap().len(), 2);
+        {
+            let cleanup_received = cleanup_received.lock().unwrap();
+            assert_eq!(cleanup_received.len(), 1);
+            assert!(cleanup_received[0].starts_with("DELETE /upload?filename=report+1.txt "));
+        }
+
+        // Without the option a conflict is not retried
+        let (endpoint, received) = spawn_server(vec![CONFLICT_RESPONSE]).await;
+        api_config.endpoint = endpoint;
+        api_config.cleanup_on_conflict = false;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+        let err = uploader
+            .upload_bytes(b"data".to_vec(), "report 1.txt")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<UploadError>(),
+            Some(UploadError::Conflict { .. })
+        ));
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    const REDIRECT_RESPONSE: &str = "HTTP/1.1 307 Temporary Redirect\r\nLocation: /moved\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+    #[tokio::test]
+    async fn test_redirect_resends_body_when_following() {
+        let (endpoint, received) = spawn_server(vec![REDIRECT_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.follow_redirects = true;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        let result = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await;
+
+        assert!(result.is_ok());
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert!(received[1].starts_with("POST /moved"));
+        assert!(received[1].contains("0123456789"));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_is_error_when_not_following() {
+        let (endpoint, received) = spawn_server(vec![REDIRECT_RESPONSE, OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        let result = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_total_timeout_stops_retries() {
+        let (endpoint, received) = spawn_server(vec![SERVER_ERROR_RESPONSE]).await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.total_timeout_secs = Some(1);
+        retry_config.max_attempts = 10;
+        retry_config.initial_backoff_secs = 5;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        let err = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("deadline exceeded"));
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_final_failure_keeps_status_and_body() {
+        let (endpoint, _received) = spawn_server(vec![SERVER_ERROR_RESPONSE]).await;
+        let (mut api_config, mut retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        retry_config.initial_backoff_secs = 0;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        let err = uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap_err();
+
+        let message = format!("{:#}", err);
+        assert!(message.starts_with("Upload failed after 3 attempts"));
+        assert!(message.contains("500") && message.contains("fail"));
+        match err.downcast_ref::<UploadError>() {
+            Some(UploadError::Server { status, body }) => {
+                assert_eq!(status.as_u16(), 500);
+                assert_eq!(body, "fail");
+            }
+            other => panic!("expected a server error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_file_to_uses_given_endpoint() {
+        let (primary, primary_received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (secondary, secondary_received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = primary;
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("raw original");
+        let result = uploader
+            .upload_file_to(&secondary, file.path(), "test.txt", &[])
+            .await;
+
+        assert!(result.is_ok());
+        assert!(primary_received.lock().unwrap().is_empty());
+        assert!(secondary_received.lock().unwrap()[0].contains("raw original"));
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_from_command() {
+        let (endpoint, received) = spawn_server(vec![OK_RESPONSE]).await;
+        let (mut api_config, retry_config) = create_test_config();
+        api_config.endpoint = endpoint;
+        api_config.auth = "bearer".to_string();
+        api_config.bearer_token_command = Some("echo secret-token".to_string());
+        let uploader = Uploader::new(&api_config, &retry_config).unwrap();
+
+        let file = create_test_file("0123456789");
+        uploader
+            .upload_file(file.path(), "test.txt", &[], &[])
+            .await
+            .unwrap();
+
+        let request = received.lock().unwrap()[0].to_lowercase();
+        assert!(request.contains("authorization: bearer secret-token"));
+    }
 }